@@ -0,0 +1,334 @@
+//! the crypto behind signing, verifying and encrypting, one `CryptoBackend`
+//! per cargo feature: `openssl` (the default), `ring` and `rust-crypto`
+//!
+//! with several features on, an operation goes to the first backend, in that
+//! order, that supports it, e.g. ES512 goes to `rust-crypto` when only `ring`
+//! and `rust-crypto` are on
+
+use std::fmt;
+
+use super::errors::*;
+use super::header::Algorithm;
+use super::material::{KeyMaterial, EcCurve, EdCurve};
+use super::jwe::KeyManagement;
+
+#[cfg(feature = "openssl")]
+mod openssl;
+#[cfg(feature = "ring")]
+mod ring;
+#[cfg(feature = "rust-crypto")]
+mod rust_crypto;
+
+#[cfg(feature = "openssl")]
+pub use self::openssl::OpensslBackend;
+#[cfg(feature = "ring")]
+pub use self::ring::RingBackend;
+#[cfg(feature = "rust-crypto")]
+pub use self::rust_crypto::RustCryptoBackend;
+
+/// a crypto library able to sign and verify JWS, every function returns
+/// `UnsupportedAlgorithm` for what the library can not do, so the next
+/// backend gets a chance
+pub trait CryptoBackend {
+    /// whether `alg` can be signed and verified at all
+    fn supports(alg: Algorithm) -> bool;
+
+    /// reject EC points off their curve and private keys whose public part
+    /// is not the one stored with them
+    fn check_key(key: &KeyMaterial) -> Result<()>;
+
+    /// the affine coordinates of the public point of the private scalar `d`
+    fn ec_public_key(curve: EcCurve, d: &[u8]) -> Result<(Vec<u8>, Vec<u8>)>;
+
+    /// the public key of the Ed25519 or Ed448 private key `d`
+    fn ed_public_key(curve: EdCurve, d: &[u8]) -> Result<Vec<u8>>;
+
+    /// the signature of `data`, ECDSA signatures are `r || s` as RFC 7518 wants
+    fn sign(key: &KeyMaterial, alg: Algorithm, data: &[u8]) -> Result<Vec<u8>>;
+
+    /// `InvalidSignature` unless `sig` is the signature of `data` by `key`
+    fn verify(key: &KeyMaterial, alg: Algorithm, data: &[u8], sig: &[u8]) -> Result<()>;
+
+    /// `len` bytes from a cryptographically secure generator
+    fn random(len: usize) -> Result<Vec<u8>>;
+
+    // the JWE primitives, a backend without them leaves them to the next one
+
+    /// AES-GCM with a 128, 192 or 256 bit `key`, the ciphertext and the 16 byte tag
+    fn aes_gcm_encrypt(_key: &[u8], _iv: &[u8], _aad: &[u8], _plaintext: &[u8])
+                       -> Result<(Vec<u8>, Vec<u8>)> {
+        Err(Error::UnsupportedAlgorithm)
+    }
+
+    /// the plaintext, `DecryptionFailed` unless `tag` authenticates `ciphertext` and `aad`
+    fn aes_gcm_decrypt(_key: &[u8], _iv: &[u8], _aad: &[u8], _ciphertext: &[u8], _tag: &[u8])
+                       -> Result<Vec<u8>> {
+        Err(Error::UnsupportedAlgorithm)
+    }
+
+    /// AES-CBC with PKCS#7 padding, unauthenticated, JWE adds an HMAC on top
+    fn aes_cbc_encrypt(_key: &[u8], _iv: &[u8], _plaintext: &[u8]) -> Result<Vec<u8>> {
+        Err(Error::UnsupportedAlgorithm)
+    }
+
+    /// the plaintext, `DecryptionFailed` when the padding is wrong
+    fn aes_cbc_decrypt(_key: &[u8], _iv: &[u8], _ciphertext: &[u8]) -> Result<Vec<u8>> {
+        Err(Error::UnsupportedAlgorithm)
+    }
+
+    /// RSAES-OAEP of `data` to the public part of `key`, with SHA-1 for
+    /// `RSA_OAEP` and SHA-256 for `RSA_OAEP_256`
+    fn rsa_oaep_encrypt(_key: &KeyMaterial, _alg: KeyManagement, _data: &[u8]) -> Result<Vec<u8>> {
+        Err(Error::UnsupportedAlgorithm)
+    }
+
+    /// `DecryptionFailed` whatever is wrong with `data`
+    fn rsa_oaep_decrypt(_key: &KeyMaterial, _alg: KeyManagement, _data: &[u8]) -> Result<Vec<u8>> {
+        Err(Error::UnsupportedAlgorithm)
+    }
+
+    /// SHA-256 of `data`, for the Concat KDF of ECDH-ES
+    fn sha256(_data: &[u8]) -> Result<Vec<u8>> {
+        Err(Error::UnsupportedAlgorithm)
+    }
+
+    /// a fresh EC private key, the ephemeral key of ECDH-ES
+    fn ec_generate_key(_curve: EcCurve) -> Result<KeyMaterial> {
+        Err(Error::UnsupportedAlgorithm)
+    }
+
+    /// the shared secret, the x coordinate of `d * peer`, between the private
+    /// EC `key` and the public EC `peer`, which must be rejected when it is
+    /// not on the curve, a point of a weaker curve would give away `d`
+    fn ecdh(_key: &KeyMaterial, _peer: &KeyMaterial) -> Result<Vec<u8>> {
+        Err(Error::UnsupportedAlgorithm)
+    }
+
+    /// AES Key Wrap of RFC 3394 with the 128, 192 or 256 bit `kek`
+    fn aes_key_wrap(_kek: &[u8], _key: &[u8]) -> Result<Vec<u8>> {
+        Err(Error::UnsupportedAlgorithm)
+    }
+
+    /// the unwrapped key, `DecryptionFailed` unless its integrity check passes
+    fn aes_key_unwrap(_kek: &[u8], _wrapped: &[u8]) -> Result<Vec<u8>> {
+        Err(Error::UnsupportedAlgorithm)
+    }
+}
+
+/// `CryptoBackend::ec_public_key`
+pub type EcPublicKeyFn = fn(EcCurve, &[u8]) -> Result<(Vec<u8>, Vec<u8>)>;
+/// `CryptoBackend::aes_gcm_encrypt`
+pub type AesGcmEncryptFn = fn(&[u8], &[u8], &[u8], &[u8]) -> Result<(Vec<u8>, Vec<u8>)>;
+/// `CryptoBackend::aes_gcm_decrypt`
+pub type AesGcmDecryptFn = fn(&[u8], &[u8], &[u8], &[u8], &[u8]) -> Result<Vec<u8>>;
+/// `CryptoBackend::aes_cbc_encrypt` and `CryptoBackend::aes_cbc_decrypt`
+pub type AesCbcFn = fn(&[u8], &[u8], &[u8]) -> Result<Vec<u8>>;
+
+/// the functions of one `CryptoBackend`, to go through the enabled ones
+#[derive(Clone, Copy)]
+pub struct Backend {
+    pub name: &'static str,
+    pub supports: fn(Algorithm) -> bool,
+    pub check_key: fn(&KeyMaterial) -> Result<()>,
+    pub ec_public_key: EcPublicKeyFn,
+    pub ed_public_key: fn(EdCurve, &[u8]) -> Result<Vec<u8>>,
+    pub sign: fn(&KeyMaterial, Algorithm, &[u8]) -> Result<Vec<u8>>,
+    pub verify: fn(&KeyMaterial, Algorithm, &[u8], &[u8]) -> Result<()>,
+    pub random: fn(usize) -> Result<Vec<u8>>,
+    pub aes_gcm_encrypt: AesGcmEncryptFn,
+    pub aes_gcm_decrypt: AesGcmDecryptFn,
+    pub aes_cbc_encrypt: AesCbcFn,
+    pub aes_cbc_decrypt: AesCbcFn,
+    pub rsa_oaep_encrypt: fn(&KeyMaterial, KeyManagement, &[u8]) -> Result<Vec<u8>>,
+    pub rsa_oaep_decrypt: fn(&KeyMaterial, KeyManagement, &[u8]) -> Result<Vec<u8>>,
+    pub sha256: fn(&[u8]) -> Result<Vec<u8>>,
+    pub ec_generate_key: fn(EcCurve) -> Result<KeyMaterial>,
+    pub ecdh: fn(&KeyMaterial, &KeyMaterial) -> Result<Vec<u8>>,
+    pub aes_key_wrap: fn(&[u8], &[u8]) -> Result<Vec<u8>>,
+    pub aes_key_unwrap: fn(&[u8], &[u8]) -> Result<Vec<u8>>,
+}
+
+impl fmt::Debug for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Backend({})", self.name)
+    }
+}
+
+macro_rules! backend {
+    ($name:expr, $backend:ty) => (Backend {
+        name: $name,
+        supports: <$backend>::supports,
+        check_key: <$backend>::check_key,
+        ec_public_key: <$backend>::ec_public_key,
+        ed_public_key: <$backend>::ed_public_key,
+        sign: <$backend>::sign,
+        verify: <$backend>::verify,
+        random: <$backend>::random,
+        aes_gcm_encrypt: <$backend>::aes_gcm_encrypt,
+        aes_gcm_decrypt: <$backend>::aes_gcm_decrypt,
+        aes_cbc_encrypt: <$backend>::aes_cbc_encrypt,
+        aes_cbc_decrypt: <$backend>::aes_cbc_decrypt,
+        rsa_oaep_encrypt: <$backend>::rsa_oaep_encrypt,
+        rsa_oaep_decrypt: <$backend>::rsa_oaep_decrypt,
+        sha256: <$backend>::sha256,
+        ec_generate_key: <$backend>::ec_generate_key,
+        ecdh: <$backend>::ecdh,
+        aes_key_wrap: <$backend>::aes_key_wrap,
+        aes_key_unwrap: <$backend>::aes_key_unwrap,
+    })
+}
+
+/// the enabled backends, in the order they are tried
+pub static BACKENDS: &[Backend] = &[
+    #[cfg(feature = "openssl")]
+    backend!("openssl", OpensslBackend),
+    #[cfg(feature = "ring")]
+    backend!("ring", RingBackend),
+    #[cfg(feature = "rust-crypto")]
+    backend!("rust-crypto", RustCryptoBackend),
+];
+
+// the result of the first backend that does not give `UnsupportedAlgorithm`
+fn first<T, F: Fn(&Backend) -> Result<T>>(f: F) -> Result<T> {
+    for backend in BACKENDS {
+        match f(backend) {
+            Err(Error::UnsupportedAlgorithm) => continue,
+            result => return result,
+        }
+    }
+    Err(Error::UnsupportedAlgorithm)
+}
+
+/// the output length of the hash of `alg` in bytes, `None` if it has none
+pub fn digest_len(alg: Algorithm) -> Option<usize> {
+    match alg {
+        Algorithm::HS256
+            | Algorithm::RS256
+            | Algorithm::PS256
+            | Algorithm::ES256
+            | Algorithm::ES256K => Some(32),
+        Algorithm::HS384
+            | Algorithm::RS384
+            | Algorithm::PS384
+            | Algorithm::ES384 => Some(48),
+        Algorithm::HS512
+            | Algorithm::RS512
+            | Algorithm::PS512
+            | Algorithm::ES512 => Some(64),
+        // EdDSA hashes inside the signature scheme, there is no digest to pick
+        Algorithm::EdDSA | Algorithm::None => None,
+    }
+}
+
+// the errors every backend gives for the same mistakes
+
+#[cfg(any(feature = "ring", feature = "rust-crypto"))]
+fn rejected<E: fmt::Display>(err: E) -> Error {
+    Error::InvalidKey(err.to_string())
+}
+
+pub(crate) fn not_private() -> Error {
+    Error::InvalidKey("a private key is required".to_string())
+}
+
+fn invalid_aes_key() -> Error {
+    Error::InvalidKey("an AES key has 16, 24 or 32 bytes".to_string())
+}
+
+// the key must be of the family of `alg`, and for ECDSA of its curve: a key
+// of another curve would give malformed signatures, or valid ones for the
+// wrong algorithm
+pub fn check_algorithm(key: &KeyMaterial, alg: Algorithm) -> Result<()> {
+    if key.family() != alg.family() {
+        return Err(Error::KeyFamilyMismatch);
+    }
+    if let KeyMaterial::Ec {curve, ..} = *key {
+        if EcCurve::for_algorithm(alg) != Some(curve) {
+            return Err(Error::KeyAlgorithmMismatch(alg));
+        }
+    }
+    Ok(())
+}
+
+// a key no backend can check is left to fail when it is used, ring can not
+// check RSA and Ed25519 public keys before it verifies with them
+pub fn check_key(key: &KeyMaterial) -> Result<()> {
+    match first(|backend| (backend.check_key)(key)) {
+        Err(Error::UnsupportedAlgorithm) => Ok(()),
+        result => result,
+    }
+}
+
+pub fn ec_public_key(curve: EcCurve, d: &[u8]) -> Result<(Vec<u8>, Vec<u8>)> {
+    first(|backend| (backend.ec_public_key)(curve, d))
+}
+
+pub fn ed_public_key(curve: EdCurve, d: &[u8]) -> Result<Vec<u8>> {
+    first(|backend| (backend.ed_public_key)(curve, d))
+}
+
+pub fn sign(key: &KeyMaterial, alg: Algorithm, data: &[u8]) -> Result<Vec<u8>> {
+    check_algorithm(key, alg)?;
+    first(|backend| if (backend.supports)(alg) {
+        (backend.sign)(key, alg, data)
+    } else {
+        Err(Error::UnsupportedAlgorithm)
+    })
+}
+
+pub fn verify(key: &KeyMaterial, alg: Algorithm, data: &[u8], sig: &[u8]) -> Result<()> {
+    check_algorithm(key, alg)?;
+    first(|backend| if (backend.supports)(alg) {
+        (backend.verify)(key, alg, data, sig)
+    } else {
+        Err(Error::UnsupportedAlgorithm)
+    })
+}
+
+pub fn random(len: usize) -> Result<Vec<u8>> {
+    first(|backend| (backend.random)(len))
+}
+
+pub fn aes_gcm_encrypt(key: &[u8], iv: &[u8], aad: &[u8], plaintext: &[u8]) -> Result<(Vec<u8>, Vec<u8>)> {
+    first(|backend| (backend.aes_gcm_encrypt)(key, iv, aad, plaintext))
+}
+
+pub fn aes_gcm_decrypt(key: &[u8], iv: &[u8], aad: &[u8], ciphertext: &[u8], tag: &[u8]) -> Result<Vec<u8>> {
+    first(|backend| (backend.aes_gcm_decrypt)(key, iv, aad, ciphertext, tag))
+}
+
+pub fn aes_cbc_encrypt(key: &[u8], iv: &[u8], plaintext: &[u8]) -> Result<Vec<u8>> {
+    first(|backend| (backend.aes_cbc_encrypt)(key, iv, plaintext))
+}
+
+pub fn aes_cbc_decrypt(key: &[u8], iv: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>> {
+    first(|backend| (backend.aes_cbc_decrypt)(key, iv, ciphertext))
+}
+
+pub fn rsa_oaep_encrypt(key: &KeyMaterial, alg: KeyManagement, data: &[u8]) -> Result<Vec<u8>> {
+    first(|backend| (backend.rsa_oaep_encrypt)(key, alg, data))
+}
+
+pub fn rsa_oaep_decrypt(key: &KeyMaterial, alg: KeyManagement, data: &[u8]) -> Result<Vec<u8>> {
+    first(|backend| (backend.rsa_oaep_decrypt)(key, alg, data))
+}
+
+pub fn sha256(data: &[u8]) -> Result<Vec<u8>> {
+    first(|backend| (backend.sha256)(data))
+}
+
+pub fn ec_generate_key(curve: EcCurve) -> Result<KeyMaterial> {
+    first(|backend| (backend.ec_generate_key)(curve))
+}
+
+pub fn ecdh(key: &KeyMaterial, peer: &KeyMaterial) -> Result<Vec<u8>> {
+    first(|backend| (backend.ecdh)(key, peer))
+}
+
+pub fn aes_key_wrap(kek: &[u8], key: &[u8]) -> Result<Vec<u8>> {
+    first(|backend| (backend.aes_key_wrap)(kek, key))
+}
+
+pub fn aes_key_unwrap(kek: &[u8], wrapped: &[u8]) -> Result<Vec<u8>> {
+    first(|backend| (backend.aes_key_unwrap)(kek, wrapped))
+}
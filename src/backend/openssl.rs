@@ -0,0 +1,449 @@
+use openssl::aes::{self, AesKey};
+use openssl::bn::{BigNum, BigNumContext};
+use openssl::derive::Deriver;
+use openssl::encrypt::{Decrypter, Encrypter};
+use openssl::ec::{EcGroup, EcKey, EcPoint};
+use openssl::hash::{MessageDigest, hash};
+use openssl::nid::Nid;
+use openssl::pkey::{Id, PKey, PKeyRef, HasPrivate, HasPublic, Private, Public};
+use openssl::rsa::{Padding, Rsa};
+use openssl::sign::{Signer, Verifier, RsaPssSaltlen};
+use openssl::memcmp::eq;
+use openssl::rand::rand_bytes;
+use openssl::symm::{self, Cipher};
+
+use super::{CryptoBackend, digest_len, not_private, invalid_aes_key};
+use super::super::errors::*;
+use super::super::header::Algorithm;
+use super::super::jwe::KeyManagement;
+use super::super::material::{KeyMaterial, EcCurve, EdCurve, pad};
+use super::super::utils::{
+    ecdsa_der_to_raw,
+    ecdsa_raw_to_der,
+    P256_ORDER_LEN,
+    P384_ORDER_LEN,
+    P521_ORDER_LEN,
+    SECP256K1_ORDER_LEN,
+};
+
+/// the `openssl` crate, every algorithm, Ed448 included
+pub struct OpensslBackend;
+
+// the SHA-2 hash of the length `digest_len` gives
+fn create_message_digest(alg: Algorithm) -> Option<MessageDigest> {
+    match digest_len(alg) {
+        Some(32) => Some(MessageDigest::sha256()),
+        Some(48) => Some(MessageDigest::sha384()),
+        Some(64) => Some(MessageDigest::sha512()),
+        _ => None,
+    }
+}
+
+fn required_message_digest(alg: Algorithm) -> Result<MessageDigest> {
+    create_message_digest(alg).ok_or(Error::UnsupportedAlgorithm)
+}
+
+fn get_order_len(alg:Algorithm) -> Result<usize> {
+    match alg {
+        Algorithm::ES256 => Ok(P256_ORDER_LEN),
+        Algorithm::ES384 => Ok(P384_ORDER_LEN),
+        Algorithm::ES512 => Ok(P521_ORDER_LEN),
+        Algorithm::ES256K => Ok(SECP256K1_ORDER_LEN),
+        _ => Err(Error::UnsupportedAlgorithm),
+    }
+}
+
+fn curve_nid(curve: EcCurve) -> Nid {
+    match curve {
+        EcCurve::P256 => Nid::X9_62_PRIME256V1,
+        EcCurve::P384 => Nid::SECP384R1,
+        EcCurve::P521 => Nid::SECP521R1,
+        EcCurve::Secp256k1 => Nid::SECP256K1,
+    }
+}
+
+fn ed_id(curve: EdCurve) -> Id {
+    match curve {
+        EdCurve::Ed25519 => Id::ED25519,
+        EdCurve::Ed448 => Id::ED448,
+    }
+}
+
+fn bignum(bytes: &[u8]) -> Result<BigNum> {
+    Ok(BigNum::from_slice(bytes)?)
+}
+
+// rejects points that are not on the curve
+fn ec_public(curve: EcCurve, x: &[u8], y: &[u8]) -> Result<EcKey<Public>> {
+    let group = EcGroup::from_curve_name(curve_nid(curve))?;
+    let (x, y) = (bignum(x)?, bignum(y)?);
+    Ok(EcKey::from_public_key_affine_coordinates(&group, &x, &y)?)
+}
+
+fn private_key(key: &KeyMaterial) -> Result<PKey<Private>> {
+    match *key {
+        KeyMaterial::Secret(ref secret) => Ok(PKey::hmac(secret)?),
+        KeyMaterial::Rsa {ref n, ref e, private: Some(ref private)} => {
+            let rsa = Rsa::from_private_components(
+                bignum(n)?, bignum(e)?, bignum(&private.d)?,
+                bignum(&private.p)?, bignum(&private.q)?,
+                bignum(&private.dp)?, bignum(&private.dq)?, bignum(&private.qi)?)?;
+            Ok(PKey::from_rsa(rsa)?)
+        }
+        KeyMaterial::Ec {curve, ref x, ref y, d: Some(ref d)} => {
+            let public = ec_public(curve, x, y)?;
+            let d = bignum(d)?;
+            let ec = EcKey::from_private_components(public.group(), &d, public.public_key())?;
+            ec.check_key()?;
+            Ok(PKey::from_ec_key(ec)?)
+        }
+        KeyMaterial::Ed {curve, d: Some(ref d), ..} => {
+            Ok(PKey::private_key_from_raw_bytes(d, ed_id(curve))?)
+        }
+        _ => Err(not_private()),
+    }
+}
+
+fn public_key(key: &KeyMaterial) -> Result<PKey<Public>> {
+    match *key {
+        KeyMaterial::Secret(_) => Err(Error::KeyFamilyMismatch),
+        KeyMaterial::Rsa {ref n, ref e, ..} => {
+            let rsa = Rsa::from_public_components(bignum(n)?, bignum(e)?)?;
+            Ok(PKey::from_rsa(rsa)?)
+        }
+        KeyMaterial::Ec {curve, ref x, ref y, ..} => {
+            Ok(PKey::from_ec_key(ec_public(curve, x, y)?)?)
+        }
+        KeyMaterial::Ed {curve, ref x, ..} => {
+            Ok(PKey::public_key_from_raw_bytes(x, ed_id(curve))?)
+        }
+    }
+}
+
+fn hs_signature<T: HasPrivate>(key: &PKeyRef<T>,
+                               data: &[u8],
+                               alg: Algorithm) -> Result<Vec<u8>> {
+    let message_digest = required_message_digest(alg)?;
+    let mut signer = Signer::new(message_digest, key)?;
+    signer.update(data)?;
+    let byte_vec = signer.sign_to_vec()?;
+    Ok(byte_vec)
+}
+
+fn hs_verify<T: HasPrivate>(key: &PKeyRef<T>,
+                            data: &[u8],
+                            sig: &[u8],
+                            alg: Algorithm) -> Result<()> {
+    let digest_u8s = &hs_signature(key, data, alg)?;
+    if digest_u8s.len() != sig.len()
+        || !eq(digest_u8s, sig) {
+        return Err(Error::InvalidSignature);
+    }
+    Ok(())
+}
+
+fn is_pss(alg: Algorithm) -> bool {
+    matches!(alg, Algorithm::PS256 | Algorithm::PS384 | Algorithm::PS512)
+}
+
+fn rsa_signature<T: HasPrivate>(key: &PKeyRef<T>,
+                                data: &[u8],
+                                alg: Algorithm) -> Result<Vec<u8>> {
+    let message_digest = required_message_digest(alg)?;
+    let mut signer = Signer::new(message_digest, key)?;
+    // RFC 7518 3.5: MGF1 with the same hash, salt as long as the hash
+    if is_pss(alg) {
+        signer.set_rsa_padding(Padding::PKCS1_PSS)?;
+        signer.set_rsa_mgf1_md(message_digest)?;
+        signer.set_rsa_pss_saltlen(RsaPssSaltlen::DIGEST_LENGTH)?;
+    }
+    signer.update(data)?;
+    Ok(signer.sign_to_vec()?)
+}
+
+fn rsa_verify<T: HasPublic>(key: &PKeyRef<T>,
+                            data: &[u8],
+                            sig: &[u8],
+                            alg: Algorithm) -> Result<()> {
+    let message_digest = required_message_digest(alg)?;
+    let mut verifier = Verifier::new(message_digest, key)?;
+    if is_pss(alg) {
+        verifier.set_rsa_padding(Padding::PKCS1_PSS)?;
+        verifier.set_rsa_mgf1_md(message_digest)?;
+        verifier.set_rsa_pss_saltlen(RsaPssSaltlen::DIGEST_LENGTH)?;
+    }
+    verifier.update(data)?;
+    let b = verifier.verify(sig)?;
+    if b {
+        Ok(())
+    } else {
+        Err(Error::InvalidSignature)
+    }
+}
+
+fn ecdsa_signature<T: HasPrivate>(key: &PKeyRef<T>,
+                                  data: &[u8],
+                                  alg: Algorithm) -> Result<Vec<u8>> {
+    let message_digest = required_message_digest(alg)?;
+    let mut signer = Signer::new(message_digest, key)?;
+    signer.update(data)?;
+    let result = signer.sign_to_vec()?;
+    ecdsa_der_to_raw(&result, get_order_len(alg)?)
+}
+
+fn ecdsa_verify<T: HasPublic>(key: &PKeyRef<T>,
+                              data: &[u8],
+                              sig: &[u8],
+                              alg: Algorithm) -> Result<()> {
+    let message_digest = required_message_digest(alg)?;
+    let mut verifier = Verifier::new(message_digest, key)?;
+    verifier.update(data)?;
+
+    let der_sig = ecdsa_raw_to_der(sig, get_order_len(alg)?)?;
+    let b = verifier.verify(&der_sig)?;
+    if b {
+        Ok(())
+    } else {
+        Err(Error::InvalidSignature)
+    }
+}
+
+// Ed25519 and Ed448 sign the whole message in one shot
+fn eddsa_signature<T: HasPrivate>(key: &PKeyRef<T>,
+                                  data: &[u8]) -> Result<Vec<u8>> {
+    let mut signer = Signer::new_without_digest(key)?;
+    Ok(signer.sign_oneshot_to_vec(data)?)
+}
+
+fn eddsa_verify<T: HasPublic>(key: &PKeyRef<T>,
+                              data: &[u8],
+                              sig: &[u8]) -> Result<()> {
+    let mut verifier = Verifier::new_without_digest(key)?;
+    // openssl reports a malformed signature as an error, not as a mismatch
+    match verifier.verify_oneshot(sig, data) {
+        Ok(true) => Ok(()),
+        _ => Err(Error::InvalidSignature),
+    }
+}
+
+fn aes_gcm(key: &[u8]) -> Result<Cipher> {
+    match key.len() {
+        16 => Ok(Cipher::aes_128_gcm()),
+        24 => Ok(Cipher::aes_192_gcm()),
+        32 => Ok(Cipher::aes_256_gcm()),
+        _ => Err(invalid_aes_key()),
+    }
+}
+
+fn aes_cbc(key: &[u8]) -> Result<Cipher> {
+    match key.len() {
+        16 => Ok(Cipher::aes_128_cbc()),
+        24 => Ok(Cipher::aes_192_cbc()),
+        32 => Ok(Cipher::aes_256_cbc()),
+        _ => Err(invalid_aes_key()),
+    }
+}
+
+// the hash of OAEP and of its MGF1
+fn oaep_digest(alg: KeyManagement) -> Result<MessageDigest> {
+    match alg {
+        KeyManagement::RSA_OAEP => Ok(MessageDigest::sha1()),
+        KeyManagement::RSA_OAEP_256 => Ok(MessageDigest::sha256()),
+        _ => Err(Error::UnsupportedAlgorithm),
+    }
+}
+
+fn oaep_encrypt<T: HasPublic>(key: &PKeyRef<T>, md: MessageDigest, data: &[u8]) -> Result<Vec<u8>> {
+    let mut encrypter = Encrypter::new(key)?;
+    encrypter.set_rsa_padding(Padding::PKCS1_OAEP)?;
+    encrypter.set_rsa_oaep_md(md)?;
+    encrypter.set_rsa_mgf1_md(md)?;
+    let mut result = vec![0u8; encrypter.encrypt_len(data)?];
+    let len = encrypter.encrypt(data, &mut result)?;
+    result.truncate(len);
+    Ok(result)
+}
+
+fn oaep_decrypt<T: HasPrivate>(key: &PKeyRef<T>, md: MessageDigest, data: &[u8]) -> Result<Vec<u8>> {
+    let mut decrypter = Decrypter::new(key)?;
+    decrypter.set_rsa_padding(Padding::PKCS1_OAEP)?;
+    decrypter.set_rsa_oaep_md(md)?;
+    decrypter.set_rsa_mgf1_md(md)?;
+    let mut result = vec![0u8; decrypter.decrypt_len(data)?];
+    let len = decrypter.decrypt(data, &mut result).map_err(|_| Error::DecryptionFailed)?;
+    result.truncate(len);
+    Ok(result)
+}
+
+// RFC 3394 wraps keys of at least two 64 bit blocks, openssl panics on
+// anything else
+fn check_key_wrap_len(len: usize) -> Result<()> {
+    if len < 16 || len % 8 != 0 {
+        return Err(Error::InvalidKey("a wrapped key has a multiple of 8 bytes, at least 16".to_string()));
+    }
+    Ok(())
+}
+
+fn aes_key_len(kek: &[u8]) -> Result<()> {
+    match kek.len() {
+        16 | 24 | 32 => Ok(()),
+        _ => Err(invalid_aes_key()),
+    }
+}
+
+// JWE always uses the full 16 byte GCM tag
+const GCM_TAG_LEN: usize = 16;
+
+impl CryptoBackend for OpensslBackend {
+    fn supports(alg: Algorithm) -> bool {
+        alg != Algorithm::None
+    }
+
+    fn check_key(key: &KeyMaterial) -> Result<()> {
+        if key.is_private() {
+            private_key(key)?;
+        } else {
+            public_key(key)?;
+        }
+        if let KeyMaterial::Ed {curve, ref x, d: Some(ref d)} = *key {
+            if OpensslBackend::ed_public_key(curve, d)? != *x {
+                return Err(Error::InvalidKey("the public key does not match the private key".to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    fn ec_public_key(curve: EcCurve, d: &[u8]) -> Result<(Vec<u8>, Vec<u8>)> {
+        let group = EcGroup::from_curve_name(curve_nid(curve))?;
+        let mut ctx = BigNumContext::new()?;
+        let mut point = EcPoint::new(&group)?;
+        let d = bignum(d)?;
+        point.mul_generator2(&group, &d, &mut ctx)?;
+        let mut x = BigNum::new()?;
+        let mut y = BigNum::new()?;
+        point.affine_coordinates_gfp(&group, &mut x, &mut y, &mut ctx)?;
+        let len = curve.coordinate_len();
+        Ok((pad(&x.to_vec(), len)?, pad(&y.to_vec(), len)?))
+    }
+
+    fn ed_public_key(curve: EdCurve, d: &[u8]) -> Result<Vec<u8>> {
+        let pkey = PKey::private_key_from_raw_bytes(d, ed_id(curve))?;
+        Ok(pkey.raw_public_key()?)
+    }
+
+    fn sign(key: &KeyMaterial, alg: Algorithm, data: &[u8]) -> Result<Vec<u8>> {
+        let pkey = private_key(key)?;
+        match *key {
+            KeyMaterial::Secret(_) => hs_signature(&*pkey, data, alg),
+            KeyMaterial::Rsa {..} => rsa_signature(&*pkey, data, alg),
+            KeyMaterial::Ec {..} => ecdsa_signature(&*pkey, data, alg),
+            KeyMaterial::Ed {..} => eddsa_signature(&*pkey, data),
+        }
+    }
+
+    fn verify(key: &KeyMaterial, alg: Algorithm, data: &[u8], sig: &[u8]) -> Result<()> {
+        match *key {
+            KeyMaterial::Secret(_) => hs_verify(&*private_key(key)?, data, sig, alg),
+            KeyMaterial::Rsa {..} => rsa_verify(&*public_key(key)?, data, sig, alg),
+            KeyMaterial::Ec {..} => ecdsa_verify(&*public_key(key)?, data, sig, alg),
+            KeyMaterial::Ed {..} => eddsa_verify(&*public_key(key)?, data, sig),
+        }
+    }
+
+    fn random(len: usize) -> Result<Vec<u8>> {
+        let mut buf = vec![0u8; len];
+        rand_bytes(&mut buf)?;
+        Ok(buf)
+    }
+
+    fn aes_gcm_encrypt(key: &[u8], iv: &[u8], aad: &[u8], plaintext: &[u8])
+                       -> Result<(Vec<u8>, Vec<u8>)> {
+        let mut tag = vec![0u8; GCM_TAG_LEN];
+        let ciphertext = symm::encrypt_aead(aes_gcm(key)?, key, Some(iv), aad, plaintext, &mut tag)?;
+        Ok((ciphertext, tag))
+    }
+
+    fn aes_gcm_decrypt(key: &[u8], iv: &[u8], aad: &[u8], ciphertext: &[u8], tag: &[u8])
+                       -> Result<Vec<u8>> {
+        if tag.len() != GCM_TAG_LEN {
+            return Err(Error::DecryptionFailed);
+        }
+        symm::decrypt_aead(aes_gcm(key)?, key, Some(iv), aad, ciphertext, tag)
+            .map_err(|_| Error::DecryptionFailed)
+    }
+
+    fn aes_cbc_encrypt(key: &[u8], iv: &[u8], plaintext: &[u8]) -> Result<Vec<u8>> {
+        Ok(symm::encrypt(aes_cbc(key)?, key, Some(iv), plaintext)?)
+    }
+
+    fn aes_cbc_decrypt(key: &[u8], iv: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>> {
+        symm::decrypt(aes_cbc(key)?, key, Some(iv), ciphertext).map_err(|_| Error::DecryptionFailed)
+    }
+
+    fn rsa_oaep_encrypt(key: &KeyMaterial, alg: KeyManagement, data: &[u8]) -> Result<Vec<u8>> {
+        let md = oaep_digest(alg)?;
+        oaep_encrypt(&*public_key(key)?, md, data)
+    }
+
+    fn rsa_oaep_decrypt(key: &KeyMaterial, alg: KeyManagement, data: &[u8]) -> Result<Vec<u8>> {
+        let md = oaep_digest(alg)?;
+        oaep_decrypt(&*private_key(key)?, md, data)
+    }
+
+    fn sha256(data: &[u8]) -> Result<Vec<u8>> {
+        Ok(hash(MessageDigest::sha256(), data)?.to_vec())
+    }
+
+    fn ec_generate_key(curve: EcCurve) -> Result<KeyMaterial> {
+        let group = EcGroup::from_curve_name(curve_nid(curve))?;
+        let key = EcKey::generate(&group)?;
+        let mut ctx = BigNumContext::new()?;
+        let mut x = BigNum::new()?;
+        let mut y = BigNum::new()?;
+        key.public_key().affine_coordinates_gfp(&group, &mut x, &mut y, &mut ctx)?;
+        let len = curve.coordinate_len();
+        Ok(KeyMaterial::Ec {
+            curve,
+            x: pad(&x.to_vec(), len)?,
+            y: pad(&y.to_vec(), len)?,
+            d: Some(pad(&key.private_key().to_vec(), len)?),
+        })
+    }
+
+    fn ecdh(key: &KeyMaterial, peer: &KeyMaterial) -> Result<Vec<u8>> {
+        let peer = match *peer {
+            KeyMaterial::Ec {curve, ref x, ref y, ..} => ec_public(curve, x, y).map_err(|_| {
+                Error::InvalidKey("the public key is not on the curve".to_string())
+            })?,
+            _ => return Err(Error::KeyFamilyMismatch),
+        };
+        let peer = PKey::from_ec_key(peer)?;
+        let key = private_key(key)?;
+        let mut deriver = Deriver::new(&key)?;
+        deriver.set_peer(&peer)?;
+        Ok(deriver.derive_to_vec()?)
+    }
+
+    fn aes_key_wrap(kek: &[u8], key: &[u8]) -> Result<Vec<u8>> {
+        aes_key_len(kek)?;
+        check_key_wrap_len(key.len())?;
+        let kek = AesKey::new_encrypt(kek).map_err(|_| Error::InvalidKey("bad AES key".to_string()))?;
+        let mut result = vec![0u8; key.len() + 8];
+        let len = aes::wrap_key(&kek, None, &mut result, key)
+            .map_err(|_| Error::Crypto("AES key wrap failed".into()))?;
+        result.truncate(len);
+        Ok(result)
+    }
+
+    fn aes_key_unwrap(kek: &[u8], wrapped: &[u8]) -> Result<Vec<u8>> {
+        aes_key_len(kek)?;
+        if wrapped.len() < 24 || wrapped.len() % 8 != 0 {
+            return Err(Error::DecryptionFailed);
+        }
+        let kek = AesKey::new_decrypt(kek).map_err(|_| Error::InvalidKey("bad AES key".to_string()))?;
+        let mut result = vec![0u8; wrapped.len() - 8];
+        let len = aes::unwrap_key(&kek, None, &mut result, wrapped).map_err(|_| Error::DecryptionFailed)?;
+        result.truncate(len);
+        Ok(result)
+    }
+}
@@ -0,0 +1,257 @@
+use ring::aead::{self, Aad, LessSafeKey, Nonce, UnboundKey};
+use ring::agreement;
+use ring::digest;
+use ring::hmac;
+use ring::rand::{SecureRandom, SystemRandom};
+use ring::rsa::{KeyPairComponents, PublicKeyComponents};
+use ring::signature::{self, EcdsaKeyPair, Ed25519KeyPair, RsaKeyPair, UnparsedPublicKey};
+
+use super::{CryptoBackend, rejected, not_private, invalid_aes_key};
+use super::super::errors::*;
+use super::super::header::Algorithm;
+use super::super::material::{KeyMaterial, EcCurve, EdCurve, ec_point_bytes};
+
+/// the `ring` crate, without ES512, ES256K, Ed448 and RSA keys under 2048 bits,
+/// nor AES-CBC, AES-192, AES Key Wrap and ECDH with a static key
+pub struct RingBackend;
+
+// ring refuses smaller moduli
+const MIN_RSA_BITS: u32 = 2048;
+
+fn hmac_algorithm(alg: Algorithm) -> Result<hmac::Algorithm> {
+    match alg {
+        Algorithm::HS256 => Ok(hmac::HMAC_SHA256),
+        Algorithm::HS384 => Ok(hmac::HMAC_SHA384),
+        Algorithm::HS512 => Ok(hmac::HMAC_SHA512),
+        _ => Err(Error::UnsupportedAlgorithm),
+    }
+}
+
+fn rsa_encoding(alg: Algorithm) -> Result<&'static dyn signature::RsaEncoding> {
+    match alg {
+        Algorithm::RS256 => Ok(&signature::RSA_PKCS1_SHA256),
+        Algorithm::RS384 => Ok(&signature::RSA_PKCS1_SHA384),
+        Algorithm::RS512 => Ok(&signature::RSA_PKCS1_SHA512),
+        Algorithm::PS256 => Ok(&signature::RSA_PSS_SHA256),
+        Algorithm::PS384 => Ok(&signature::RSA_PSS_SHA384),
+        Algorithm::PS512 => Ok(&signature::RSA_PSS_SHA512),
+        _ => Err(Error::UnsupportedAlgorithm),
+    }
+}
+
+fn rsa_parameters(alg: Algorithm) -> Result<&'static signature::RsaParameters> {
+    match alg {
+        Algorithm::RS256 => Ok(&signature::RSA_PKCS1_2048_8192_SHA256),
+        Algorithm::RS384 => Ok(&signature::RSA_PKCS1_2048_8192_SHA384),
+        Algorithm::RS512 => Ok(&signature::RSA_PKCS1_2048_8192_SHA512),
+        Algorithm::PS256 => Ok(&signature::RSA_PSS_2048_8192_SHA256),
+        Algorithm::PS384 => Ok(&signature::RSA_PSS_2048_8192_SHA384),
+        Algorithm::PS512 => Ok(&signature::RSA_PSS_2048_8192_SHA512),
+        _ => Err(Error::UnsupportedAlgorithm),
+    }
+}
+
+fn ecdsa_signing(curve: EcCurve) -> Result<&'static signature::EcdsaSigningAlgorithm> {
+    match curve {
+        EcCurve::P256 => Ok(&signature::ECDSA_P256_SHA256_FIXED_SIGNING),
+        EcCurve::P384 => Ok(&signature::ECDSA_P384_SHA384_FIXED_SIGNING),
+        _ => Err(Error::UnsupportedAlgorithm),
+    }
+}
+
+fn ecdsa_verification(curve: EcCurve) -> Result<&'static signature::EcdsaVerificationAlgorithm> {
+    match curve {
+        EcCurve::P256 => Ok(&signature::ECDSA_P256_SHA256_FIXED),
+        EcCurve::P384 => Ok(&signature::ECDSA_P384_SHA384_FIXED),
+        _ => Err(Error::UnsupportedAlgorithm),
+    }
+}
+
+// ring has no AES-192
+fn aes_gcm_key(key: &[u8]) -> Result<LessSafeKey> {
+    let alg = match key.len() {
+        16 => &aead::AES_128_GCM,
+        32 => &aead::AES_256_GCM,
+        24 => return Err(Error::UnsupportedAlgorithm),
+        _ => return Err(invalid_aes_key()),
+    };
+    Ok(LessSafeKey::new(UnboundKey::new(alg, key).map_err(rejected)?))
+}
+
+fn gcm_nonce(iv: &[u8]) -> Result<Nonce> {
+    Nonce::try_assume_unique_for_key(iv).map_err(|_| Error::UnsupportedAlgorithm)
+}
+
+fn check_rsa_size(key: &KeyMaterial) -> Result<()> {
+    match key.rsa_bits() {
+        Some(bits) if bits < MIN_RSA_BITS => Err(Error::UnsupportedAlgorithm),
+        _ => Ok(()),
+    }
+}
+
+fn rsa_key_pair(key: &KeyMaterial) -> Result<RsaKeyPair> {
+    check_rsa_size(key)?;
+    match *key {
+        KeyMaterial::Rsa {ref n, ref e, private: Some(ref private)} => {
+            RsaKeyPair::from_components(&KeyPairComponents {
+                public_key: PublicKeyComponents {n, e},
+                d: &private.d,
+                p: &private.p,
+                q: &private.q,
+                dP: &private.dp,
+                dQ: &private.dq,
+                qInv: &private.qi,
+            }).map_err(rejected)
+        }
+        _ => Err(not_private()),
+    }
+}
+
+fn ecdsa_key_pair(key: &KeyMaterial) -> Result<EcdsaKeyPair> {
+    match *key {
+        KeyMaterial::Ec {curve, ref x, ref y, d: Some(ref d)} => {
+            let alg = ecdsa_signing(curve)?;
+            EcdsaKeyPair::from_private_key_and_public_key(alg, d, &ec_point_bytes(x, y),
+                                                          &SystemRandom::new())
+                .map_err(rejected)
+        }
+        _ => Err(not_private()),
+    }
+}
+
+fn ed25519_key_pair(key: &KeyMaterial) -> Result<Ed25519KeyPair> {
+    match *key {
+        KeyMaterial::Ed {curve: EdCurve::Ed25519, ref x, d: Some(ref d)} => {
+            Ed25519KeyPair::from_seed_and_public_key(d, x).map_err(rejected)
+        }
+        KeyMaterial::Ed {curve: EdCurve::Ed25519, ..} => Err(not_private()),
+        _ => Err(Error::UnsupportedAlgorithm),
+    }
+}
+
+// ring has no way to parse an EC point on its own, an agreement with a
+// throwaway key does, and rejects points off the curve
+fn check_ec_point(curve: EcCurve, point: &[u8]) -> Result<()> {
+    let alg = match curve {
+        EcCurve::P256 => &agreement::ECDH_P256,
+        EcCurve::P384 => &agreement::ECDH_P384,
+        _ => return Err(Error::UnsupportedAlgorithm),
+    };
+    let ephemeral = agreement::EphemeralPrivateKey::generate(alg, &SystemRandom::new()).map_err(rejected)?;
+    agreement::agree_ephemeral(ephemeral, &agreement::UnparsedPublicKey::new(alg, point), |_| ())
+        .map_err(|_| Error::InvalidKey("the public key is not on the curve".to_string()))
+}
+
+impl CryptoBackend for RingBackend {
+    fn supports(alg: Algorithm) -> bool {
+        !matches!(alg, Algorithm::ES512 | Algorithm::ES256K | Algorithm::None)
+    }
+
+    // ring parses RSA and Ed25519 public keys only when it verifies with
+    // them, another backend may check them sooner
+    fn check_key(key: &KeyMaterial) -> Result<()> {
+        match *key {
+            KeyMaterial::Secret(_) => Ok(()),
+            KeyMaterial::Ec {curve, ref x, ref y, d: None} => check_ec_point(curve, &ec_point_bytes(x, y)),
+            _ if !key.is_private() => Err(Error::UnsupportedAlgorithm),
+            KeyMaterial::Rsa {..} => rsa_key_pair(key).map(|_| ()),
+            KeyMaterial::Ec {..} => ecdsa_key_pair(key).map(|_| ()),
+            KeyMaterial::Ed {..} => ed25519_key_pair(key).map(|_| ()),
+        }
+    }
+
+    fn ec_public_key(_: EcCurve, _: &[u8]) -> Result<(Vec<u8>, Vec<u8>)> {
+        Err(Error::UnsupportedAlgorithm)
+    }
+
+    fn ed_public_key(curve: EdCurve, d: &[u8]) -> Result<Vec<u8>> {
+        if curve != EdCurve::Ed25519 {
+            return Err(Error::UnsupportedAlgorithm);
+        }
+        use ring::signature::KeyPair;
+        let pair = Ed25519KeyPair::from_seed_unchecked(d).map_err(rejected)?;
+        Ok(pair.public_key().as_ref().to_vec())
+    }
+
+    fn sign(key: &KeyMaterial, alg: Algorithm, data: &[u8]) -> Result<Vec<u8>> {
+        let rng = SystemRandom::new();
+        match *key {
+            KeyMaterial::Secret(ref secret) => {
+                let key = hmac::Key::new(hmac_algorithm(alg)?, secret);
+                Ok(hmac::sign(&key, data).as_ref().to_vec())
+            }
+            KeyMaterial::Rsa {..} => {
+                let pair = rsa_key_pair(key)?;
+                let mut sig = vec![0u8; pair.public().modulus_len()];
+                pair.sign(rsa_encoding(alg)?, &rng, data, &mut sig).map_err(rejected)?;
+                Ok(sig)
+            }
+            KeyMaterial::Ec {..} => {
+                let sig = ecdsa_key_pair(key)?.sign(&rng, data).map_err(rejected)?;
+                Ok(sig.as_ref().to_vec())
+            }
+            KeyMaterial::Ed {..} => {
+                Ok(ed25519_key_pair(key)?.sign(data).as_ref().to_vec())
+            }
+        }
+    }
+
+    fn verify(key: &KeyMaterial, alg: Algorithm, data: &[u8], sig: &[u8]) -> Result<()> {
+        let result = match *key {
+            KeyMaterial::Secret(ref secret) => {
+                let key = hmac::Key::new(hmac_algorithm(alg)?, secret);
+                hmac::verify(&key, data, sig)
+            }
+            KeyMaterial::Rsa {ref n, ref e, ..} => {
+                check_rsa_size(key)?;
+                PublicKeyComponents {n, e}.verify(rsa_parameters(alg)?, data, sig)
+            }
+            KeyMaterial::Ec {curve, ref x, ref y, ..} => {
+                UnparsedPublicKey::new(ecdsa_verification(curve)?, ec_point_bytes(x, y))
+                    .verify(data, sig)
+            }
+            KeyMaterial::Ed {curve: EdCurve::Ed25519, ref x, ..} => {
+                UnparsedPublicKey::new(&signature::ED25519, x).verify(data, sig)
+            }
+            KeyMaterial::Ed {..} => return Err(Error::UnsupportedAlgorithm),
+        };
+        result.map_err(|_| Error::InvalidSignature)
+    }
+
+    fn random(len: usize) -> Result<Vec<u8>> {
+        let mut buf = vec![0u8; len];
+        SystemRandom::new().fill(&mut buf).map_err(|err| Error::Crypto(err.to_string().into()))?;
+        Ok(buf)
+    }
+
+    fn aes_gcm_encrypt(key: &[u8], iv: &[u8], aad: &[u8], plaintext: &[u8])
+                       -> Result<(Vec<u8>, Vec<u8>)> {
+        let key = aes_gcm_key(key)?;
+        let nonce = gcm_nonce(iv)?;
+        let mut ciphertext = plaintext.to_vec();
+        let tag = key.seal_in_place_separate_tag(nonce, Aad::from(aad), &mut ciphertext)
+            .map_err(|err| Error::Crypto(err.to_string().into()))?;
+        Ok((ciphertext, tag.as_ref().to_vec()))
+    }
+
+    fn aes_gcm_decrypt(key: &[u8], iv: &[u8], aad: &[u8], ciphertext: &[u8], tag: &[u8])
+                       -> Result<Vec<u8>> {
+        // ring splits the tag off the end, the length of `tag` must be checked
+        // or its bytes could be moved into the ciphertext
+        if tag.len() != aead::MAX_TAG_LEN {
+            return Err(Error::DecryptionFailed);
+        }
+        let key = aes_gcm_key(key)?;
+        let nonce = gcm_nonce(iv)?;
+        let mut sealed = ciphertext.to_vec();
+        sealed.extend_from_slice(tag);
+        let len = key.open_in_place(nonce, Aad::from(aad), &mut sealed)
+            .map_err(|_| Error::DecryptionFailed)?.len();
+        sealed.truncate(len);
+        Ok(sealed)
+    }
+
+    fn sha256(data: &[u8]) -> Result<Vec<u8>> {
+        Ok(digest::digest(&digest::SHA256, data).as_ref().to_vec())
+    }
+}
@@ -0,0 +1,461 @@
+use std::convert::TryFrom;
+
+use aes::{Aes128, Aes192, Aes256};
+use aes_gcm::AesGcm;
+use aes_kw::Kek;
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::aead::consts::U12;
+use cbc::cipher::{BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+use cbc::cipher::block_padding::Pkcs7;
+use ed25519_dalek;
+use hmac::{Hmac, Mac};
+use rand_core::{OsRng, RngCore};
+use rsa::{BigUint, Pkcs1v15Sign, Pss, RsaPrivateKey, RsaPublicKey};
+use sha2::{Digest, Sha256, Sha384, Sha512};
+
+use super::{CryptoBackend, rejected, not_private, invalid_aes_key};
+use super::super::errors::*;
+use super::super::header::Algorithm;
+use super::super::material::{KeyMaterial, EcCurve, EdCurve, ec_point_bytes};
+
+/// the pure Rust RustCrypto crates, every algorithm but Ed448 and RSA-OAEP,
+/// the RSA decryption of the rsa crate leaks timing (RUSTSEC-2023-0071)
+pub struct RustCryptoBackend;
+
+// the same functions for every curve, whose crates share no common key type
+macro_rules! ecdsa_curve {
+    ($module:ident, $krate:ident) => (
+        mod $module {
+            use $krate::ecdsa::{SigningKey, VerifyingKey, Signature};
+            use $krate::ecdsa::signature::{Signer, Verifier};
+
+            use errors::*;
+            use super::rejected;
+
+            pub fn public_point(d: &[u8]) -> Result<Vec<u8>> {
+                let key = SigningKey::from_slice(d).map_err(rejected)?;
+                Ok(VerifyingKey::from(&key).to_encoded_point(false).as_bytes().to_vec())
+            }
+
+            // rejects points that are not on the curve
+            pub fn check_point(point: &[u8]) -> Result<()> {
+                VerifyingKey::from_sec1_bytes(point).map(|_| ()).map_err(rejected)
+            }
+
+            pub fn sign(d: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+                let key = SigningKey::from_slice(d).map_err(rejected)?;
+                let sig: Signature = key.try_sign(data).map_err(rejected)?;
+                Ok(sig.to_bytes().to_vec())
+            }
+
+            pub fn verify(point: &[u8], data: &[u8], sig: &[u8]) -> Result<()> {
+                let key = VerifyingKey::from_sec1_bytes(point).map_err(rejected)?;
+                let sig = Signature::from_slice(sig).map_err(|_| Error::InvalidSignature)?;
+                // (r, s) and (r, n - s) are both valid, k256 only takes the low one
+                let sig = sig.normalize_s().unwrap_or(sig);
+                key.verify(data, &sig).map_err(|_| Error::InvalidSignature)
+            }
+        }
+    )
+}
+
+ecdsa_curve!(p256_ecdsa, p256);
+ecdsa_curve!(p384_ecdsa, p384);
+ecdsa_curve!(p521_ecdsa, p521);
+ecdsa_curve!(k256_ecdsa, k256);
+
+fn ec_public_point(curve: EcCurve, d: &[u8]) -> Result<Vec<u8>> {
+    match curve {
+        EcCurve::P256 => p256_ecdsa::public_point(d),
+        EcCurve::P384 => p384_ecdsa::public_point(d),
+        EcCurve::P521 => p521_ecdsa::public_point(d),
+        EcCurve::Secp256k1 => k256_ecdsa::public_point(d),
+    }
+}
+
+fn ec_check_point(curve: EcCurve, point: &[u8]) -> Result<()> {
+    match curve {
+        EcCurve::P256 => p256_ecdsa::check_point(point),
+        EcCurve::P384 => p384_ecdsa::check_point(point),
+        EcCurve::P521 => p521_ecdsa::check_point(point),
+        EcCurve::Secp256k1 => k256_ecdsa::check_point(point),
+    }
+}
+
+fn ecdsa_sign(curve: EcCurve, d: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+    match curve {
+        EcCurve::P256 => p256_ecdsa::sign(d, data),
+        EcCurve::P384 => p384_ecdsa::sign(d, data),
+        EcCurve::P521 => p521_ecdsa::sign(d, data),
+        EcCurve::Secp256k1 => k256_ecdsa::sign(d, data),
+    }
+}
+
+fn ecdsa_verify(curve: EcCurve, point: &[u8], data: &[u8], sig: &[u8]) -> Result<()> {
+    match curve {
+        EcCurve::P256 => p256_ecdsa::verify(point, data, sig),
+        EcCurve::P384 => p384_ecdsa::verify(point, data, sig),
+        EcCurve::P521 => p521_ecdsa::verify(point, data, sig),
+        EcCurve::Secp256k1 => k256_ecdsa::verify(point, data, sig),
+    }
+}
+
+// ECDH on the curves JWE defines it for
+macro_rules! ecdh_curve {
+    ($module:ident, $krate:ident) => (
+        mod $module {
+            use $krate::{PublicKey, SecretKey};
+            use $krate::ecdh::diffie_hellman;
+            use $krate::elliptic_curve::sec1::ToEncodedPoint;
+            use rand_core::OsRng;
+
+            use errors::*;
+            use super::rejected;
+
+            // the private scalar and the public point of a fresh key
+            pub fn generate() -> (Vec<u8>, Vec<u8>) {
+                let key = SecretKey::random(&mut OsRng);
+                (key.to_bytes().to_vec(), key.public_key().to_encoded_point(false).as_bytes().to_vec())
+            }
+
+            // `from_sec1_bytes` rejects points that are not on the curve
+            pub fn agree(d: &[u8], point: &[u8]) -> Result<Vec<u8>> {
+                let key = SecretKey::from_slice(d).map_err(rejected)?;
+                let peer = PublicKey::from_sec1_bytes(point).map_err(|_| {
+                    Error::InvalidKey("the public key is not on the curve".to_string())
+                })?;
+                Ok(diffie_hellman(key.to_nonzero_scalar(), peer.as_affine()).raw_secret_bytes().to_vec())
+            }
+        }
+    )
+}
+
+ecdh_curve!(p256_ecdh, p256);
+ecdh_curve!(p384_ecdh, p384);
+ecdh_curve!(p521_ecdh, p521);
+
+fn hmac_signature(secret: &[u8], alg: Algorithm, data: &[u8]) -> Result<Vec<u8>> {
+    macro_rules! mac {
+        ($hash:ty) => ({
+            let mut mac = <Hmac<$hash> as Mac>::new_from_slice(secret).map_err(rejected)?;
+            mac.update(data);
+            mac.finalize().into_bytes().to_vec()
+        })
+    }
+    match alg {
+        Algorithm::HS256 => Ok(mac!(Sha256)),
+        Algorithm::HS384 => Ok(mac!(Sha384)),
+        Algorithm::HS512 => Ok(mac!(Sha512)),
+        _ => Err(Error::UnsupportedAlgorithm),
+    }
+}
+
+fn hmac_verify(secret: &[u8], alg: Algorithm, data: &[u8], sig: &[u8]) -> Result<()> {
+    macro_rules! mac {
+        ($hash:ty) => ({
+            let mut mac = <Hmac<$hash> as Mac>::new_from_slice(secret).map_err(rejected)?;
+            mac.update(data);
+            mac.verify_slice(sig).map_err(|_| Error::InvalidSignature)
+        })
+    }
+    match alg {
+        Algorithm::HS256 => mac!(Sha256),
+        Algorithm::HS384 => mac!(Sha384),
+        Algorithm::HS512 => mac!(Sha512),
+        _ => Err(Error::UnsupportedAlgorithm),
+    }
+}
+
+fn biguint(bytes: &[u8]) -> BigUint {
+    BigUint::from_bytes_be(bytes)
+}
+
+fn rsa_private_key(key: &KeyMaterial) -> Result<RsaPrivateKey> {
+    match *key {
+        KeyMaterial::Rsa {ref n, ref e, private: Some(ref private)} => {
+            RsaPrivateKey::from_components(biguint(n), biguint(e), biguint(&private.d),
+                                           vec![biguint(&private.p), biguint(&private.q)])
+                .map_err(rejected)
+        }
+        _ => Err(not_private()),
+    }
+}
+
+fn rsa_public_key(key: &KeyMaterial) -> Result<RsaPublicKey> {
+    match *key {
+        KeyMaterial::Rsa {ref n, ref e, ..} => RsaPublicKey::new(biguint(n), biguint(e)).map_err(rejected),
+        _ => Err(Error::KeyFamilyMismatch),
+    }
+}
+
+fn rsa_signature(key: &RsaPrivateKey, alg: Algorithm, data: &[u8]) -> Result<Vec<u8>> {
+    // RFC 7518 3.5: MGF1 with the same hash, salt as long as the hash
+    let result = match alg {
+        Algorithm::RS256 => key.sign_with_rng(&mut OsRng, Pkcs1v15Sign::new::<Sha256>(), &Sha256::digest(data)),
+        Algorithm::RS384 => key.sign_with_rng(&mut OsRng, Pkcs1v15Sign::new::<Sha384>(), &Sha384::digest(data)),
+        Algorithm::RS512 => key.sign_with_rng(&mut OsRng, Pkcs1v15Sign::new::<Sha512>(), &Sha512::digest(data)),
+        Algorithm::PS256 => key.sign_with_rng(&mut OsRng, Pss::new::<Sha256>(), &Sha256::digest(data)),
+        Algorithm::PS384 => key.sign_with_rng(&mut OsRng, Pss::new::<Sha384>(), &Sha384::digest(data)),
+        Algorithm::PS512 => key.sign_with_rng(&mut OsRng, Pss::new::<Sha512>(), &Sha512::digest(data)),
+        _ => return Err(Error::UnsupportedAlgorithm),
+    };
+    result.map_err(rejected)
+}
+
+fn rsa_verify(key: &RsaPublicKey, alg: Algorithm, data: &[u8], sig: &[u8]) -> Result<()> {
+    let result = match alg {
+        Algorithm::RS256 => key.verify(Pkcs1v15Sign::new::<Sha256>(), &Sha256::digest(data), sig),
+        Algorithm::RS384 => key.verify(Pkcs1v15Sign::new::<Sha384>(), &Sha384::digest(data), sig),
+        Algorithm::RS512 => key.verify(Pkcs1v15Sign::new::<Sha512>(), &Sha512::digest(data), sig),
+        Algorithm::PS256 => key.verify(Pss::new::<Sha256>(), &Sha256::digest(data), sig),
+        Algorithm::PS384 => key.verify(Pss::new::<Sha384>(), &Sha384::digest(data), sig),
+        Algorithm::PS512 => key.verify(Pss::new::<Sha512>(), &Sha512::digest(data), sig),
+        _ => return Err(Error::UnsupportedAlgorithm),
+    };
+    result.map_err(|_| Error::InvalidSignature)
+}
+
+fn ed25519_bytes(bytes: &[u8]) -> Result<[u8; 32]> {
+    if bytes.len() != 32 {
+        return Err(Error::InvalidKey("malformed EdDSA key".to_string()));
+    }
+    let mut result = [0u8; 32];
+    result.copy_from_slice(bytes);
+    Ok(result)
+}
+
+fn ed25519_signing_key(curve: EdCurve, d: &[u8]) -> Result<ed25519_dalek::SigningKey> {
+    if curve != EdCurve::Ed25519 {
+        return Err(Error::UnsupportedAlgorithm);
+    }
+    Ok(ed25519_dalek::SigningKey::from_bytes(&ed25519_bytes(d)?))
+}
+
+// JWE always uses 96 bit IVs and the full 16 byte tag
+const GCM_IV_LEN: usize = 12;
+const GCM_TAG_LEN: usize = 16;
+
+impl CryptoBackend for RustCryptoBackend {
+    fn supports(alg: Algorithm) -> bool {
+        alg != Algorithm::None
+    }
+
+    fn check_key(key: &KeyMaterial) -> Result<()> {
+        match *key {
+            KeyMaterial::Secret(_) => Ok(()),
+            KeyMaterial::Rsa {private: Some(_), ..} => rsa_private_key(key).map(|_| ()),
+            KeyMaterial::Rsa {..} => rsa_public_key(key).map(|_| ()),
+            KeyMaterial::Ec {curve, ref x, ref y, ref d} => {
+                let point = ec_point_bytes(x, y);
+                ec_check_point(curve, &point)?;
+                match *d {
+                    Some(ref d) if ec_public_point(curve, d)? != point => {
+                        Err(Error::InvalidKey("the public key does not match the private key".to_string()))
+                    }
+                    _ => Ok(()),
+                }
+            }
+            KeyMaterial::Ed {curve, ref x, ref d} => {
+                if curve != EdCurve::Ed25519 {
+                    return Err(Error::UnsupportedAlgorithm);
+                }
+                ed25519_dalek::VerifyingKey::from_bytes(&ed25519_bytes(x)?).map_err(rejected)?;
+                match *d {
+                    Some(ref d) if RustCryptoBackend::ed_public_key(curve, d)? != *x => {
+                        Err(Error::InvalidKey("the public key does not match the private key".to_string()))
+                    }
+                    _ => Ok(()),
+                }
+            }
+        }
+    }
+
+    fn ec_public_key(curve: EcCurve, d: &[u8]) -> Result<(Vec<u8>, Vec<u8>)> {
+        let point = ec_public_point(curve, d)?;
+        let len = curve.coordinate_len();
+        Ok((point[1..1 + len].to_vec(), point[1 + len..].to_vec()))
+    }
+
+    fn ed_public_key(curve: EdCurve, d: &[u8]) -> Result<Vec<u8>> {
+        let key = ed25519_signing_key(curve, d)?;
+        Ok(key.verifying_key().to_bytes().to_vec())
+    }
+
+    fn sign(key: &KeyMaterial, alg: Algorithm, data: &[u8]) -> Result<Vec<u8>> {
+        match *key {
+            KeyMaterial::Secret(ref secret) => hmac_signature(secret, alg, data),
+            KeyMaterial::Rsa {..} => rsa_signature(&rsa_private_key(key)?, alg, data),
+            KeyMaterial::Ec {curve, d: Some(ref d), ..} => ecdsa_sign(curve, d, data),
+            KeyMaterial::Ed {curve, d: Some(ref d), ..} => {
+                use ed25519_dalek::Signer;
+                let key = ed25519_signing_key(curve, d)?;
+                Ok(key.sign(data).to_bytes().to_vec())
+            }
+            _ => Err(not_private()),
+        }
+    }
+
+    fn verify(key: &KeyMaterial, alg: Algorithm, data: &[u8], sig: &[u8]) -> Result<()> {
+        match *key {
+            KeyMaterial::Secret(ref secret) => hmac_verify(secret, alg, data, sig),
+            KeyMaterial::Rsa {..} => rsa_verify(&rsa_public_key(key)?, alg, data, sig),
+            KeyMaterial::Ec {curve, ref x, ref y, ..} => ecdsa_verify(curve, &ec_point_bytes(x, y), data, sig),
+            KeyMaterial::Ed {curve, ref x, ..} => {
+                if curve != EdCurve::Ed25519 {
+                    return Err(Error::UnsupportedAlgorithm);
+                }
+                let key = ed25519_dalek::VerifyingKey::from_bytes(&ed25519_bytes(x)?)
+                    .map_err(|_| Error::InvalidSignature)?;
+                let sig = ed25519_dalek::Signature::from_slice(sig).map_err(|_| Error::InvalidSignature)?;
+                key.verify_strict(data, &sig).map_err(|_| Error::InvalidSignature)
+            }
+        }
+    }
+
+    fn random(len: usize) -> Result<Vec<u8>> {
+        let mut buf = vec![0u8; len];
+        OsRng.try_fill_bytes(&mut buf).map_err(|err| Error::Crypto(Box::new(err)))?;
+        Ok(buf)
+    }
+
+    fn aes_gcm_encrypt(key: &[u8], iv: &[u8], aad: &[u8], plaintext: &[u8])
+                       -> Result<(Vec<u8>, Vec<u8>)> {
+        if iv.len() != GCM_IV_LEN {
+            return Err(Error::UnsupportedAlgorithm);
+        }
+        macro_rules! seal {
+            ($aes:ty) => ({
+                let cipher = AesGcm::<$aes, U12>::new_from_slice(key).map_err(rejected)?;
+                cipher.encrypt(iv.into(), Payload {msg: plaintext, aad})
+                    .map_err(|err| Error::Crypto(err.to_string().into()))?
+            })
+        }
+        let mut ciphertext = match key.len() {
+            16 => seal!(Aes128),
+            24 => seal!(Aes192),
+            32 => seal!(Aes256),
+            _ => return Err(invalid_aes_key()),
+        };
+        let tag = ciphertext.split_off(ciphertext.len() - GCM_TAG_LEN);
+        Ok((ciphertext, tag))
+    }
+
+    fn aes_gcm_decrypt(key: &[u8], iv: &[u8], aad: &[u8], ciphertext: &[u8], tag: &[u8])
+                       -> Result<Vec<u8>> {
+        if iv.len() != GCM_IV_LEN || tag.len() != GCM_TAG_LEN {
+            return Err(Error::DecryptionFailed);
+        }
+        let mut sealed = ciphertext.to_vec();
+        sealed.extend_from_slice(tag);
+        macro_rules! open {
+            ($aes:ty) => ({
+                let cipher = AesGcm::<$aes, U12>::new_from_slice(key).map_err(rejected)?;
+                cipher.decrypt(iv.into(), Payload {msg: &sealed, aad}).map_err(|_| Error::DecryptionFailed)
+            })
+        }
+        match key.len() {
+            16 => open!(Aes128),
+            24 => open!(Aes192),
+            32 => open!(Aes256),
+            _ => Err(invalid_aes_key()),
+        }
+    }
+
+    fn aes_cbc_encrypt(key: &[u8], iv: &[u8], plaintext: &[u8]) -> Result<Vec<u8>> {
+        macro_rules! encrypt {
+            ($aes:ty) => ({
+                let cipher = cbc::Encryptor::<$aes>::new_from_slices(key, iv).map_err(rejected)?;
+                Ok(cipher.encrypt_padded_vec_mut::<Pkcs7>(plaintext))
+            })
+        }
+        match key.len() {
+            16 => encrypt!(Aes128),
+            24 => encrypt!(Aes192),
+            32 => encrypt!(Aes256),
+            _ => Err(invalid_aes_key()),
+        }
+    }
+
+    fn aes_cbc_decrypt(key: &[u8], iv: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>> {
+        macro_rules! decrypt {
+            ($aes:ty) => ({
+                let cipher = cbc::Decryptor::<$aes>::new_from_slices(key, iv).map_err(rejected)?;
+                cipher.decrypt_padded_vec_mut::<Pkcs7>(ciphertext).map_err(|_| Error::DecryptionFailed)
+            })
+        }
+        match key.len() {
+            16 => decrypt!(Aes128),
+            24 => decrypt!(Aes192),
+            32 => decrypt!(Aes256),
+            _ => Err(invalid_aes_key()),
+        }
+    }
+
+    fn sha256(data: &[u8]) -> Result<Vec<u8>> {
+        Ok(Sha256::digest(data).to_vec())
+    }
+
+    fn ec_generate_key(curve: EcCurve) -> Result<KeyMaterial> {
+        let (d, point) = match curve {
+            EcCurve::P256 => p256_ecdh::generate(),
+            EcCurve::P384 => p384_ecdh::generate(),
+            EcCurve::P521 => p521_ecdh::generate(),
+            EcCurve::Secp256k1 => return Err(Error::UnsupportedAlgorithm),
+        };
+        let len = curve.coordinate_len();
+        Ok(KeyMaterial::Ec {
+            curve,
+            x: point[1..1 + len].to_vec(),
+            y: point[1 + len..].to_vec(),
+            d: Some(d),
+        })
+    }
+
+    fn ecdh(key: &KeyMaterial, peer: &KeyMaterial) -> Result<Vec<u8>> {
+        let (curve, d) = match *key {
+            KeyMaterial::Ec {curve, d: Some(ref d), ..} => (curve, d),
+            KeyMaterial::Ec {..} => return Err(not_private()),
+            _ => return Err(Error::KeyFamilyMismatch),
+        };
+        let point = match *peer {
+            KeyMaterial::Ec {curve: peer_curve, ref x, ref y, ..} if peer_curve == curve => ec_point_bytes(x, y),
+            _ => return Err(Error::KeyFamilyMismatch),
+        };
+        match curve {
+            EcCurve::P256 => p256_ecdh::agree(d, &point),
+            EcCurve::P384 => p384_ecdh::agree(d, &point),
+            EcCurve::P521 => p521_ecdh::agree(d, &point),
+            EcCurve::Secp256k1 => Err(Error::UnsupportedAlgorithm),
+        }
+    }
+
+    fn aes_key_wrap(kek: &[u8], key: &[u8]) -> Result<Vec<u8>> {
+        macro_rules! wrap {
+            ($aes:ty) => ({
+                let kek = Kek::<$aes>::try_from(kek).map_err(rejected)?;
+                kek.wrap_vec(key).map_err(rejected)
+            })
+        }
+        match kek.len() {
+            16 => wrap!(Aes128),
+            24 => wrap!(Aes192),
+            32 => wrap!(Aes256),
+            _ => Err(invalid_aes_key()),
+        }
+    }
+
+    fn aes_key_unwrap(kek: &[u8], wrapped: &[u8]) -> Result<Vec<u8>> {
+        macro_rules! unwrap {
+            ($aes:ty) => ({
+                let kek = Kek::<$aes>::try_from(kek).map_err(rejected)?;
+                kek.unwrap_vec(wrapped).map_err(|_| Error::DecryptionFailed)
+            })
+        }
+        match kek.len() {
+            16 => unwrap!(Aes128),
+            24 => unwrap!(Aes192),
+            32 => unwrap!(Aes256),
+            _ => Err(invalid_aes_key()),
+        }
+    }
+}
@@ -2,10 +2,27 @@ use std::default::Default;
 use serde::{Serialize};
 use serde_json;
 use serde_json::value::{Map, Value, to_value};
-use base64::{encode_config, decode_config, URL_SAFE};
 
 use super::errors::*;
-use super::utils::JWTStringConvertable;
+use super::utils::{JWTStringConvertable, base64_encode, base64_decode};
+
+/// the `aud` claim, RFC 7519 allows a single audience or a list of them
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Audience {
+    Single(String),
+    Multiple(Vec<String>),
+}
+
+impl Audience {
+    /// whether `aud` is one of the audiences
+    pub fn contains(&self, aud: &str) -> bool {
+        match *self {
+            Audience::Single(ref s) => s == aud,
+            Audience::Multiple(ref v) => v.iter().any(|s| s == aud),
+        }
+    }
+}
 
 #[allow(unused_attributes)]
 #[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
@@ -15,7 +32,7 @@ pub struct RegisteredClaim {
     pub exp: Option<u64>,
     pub nbf: Option<u64>,
     pub iat: Option<u64>,
-    pub aud: Option<String>,
+    pub aud: Option<Audience>,
     pub jti: Option<String>,
 }
 
@@ -42,7 +59,6 @@ pub struct Claim {
 /// // payload is acctually a BTreeMap<String, serde_json::Value>
 /// println!("{:?}", claim.payload.get("sub"))
 /// ```
-
 impl Claim {
     pub fn set_exp(&mut self, v: u64) -> &mut Claim {
         self.registered.exp = Some(v);
@@ -61,7 +77,12 @@ impl Claim {
         self
     }
     pub fn set_aud(&mut self, v: &str) -> &mut Claim {
-        self.registered.aud = Some(v.to_string());
+        self.registered.aud = Some(Audience::Single(v.to_string()));
+        self
+    }
+    /// set `aud` to a list, it is written as a json array even with one item
+    pub fn set_audiences(&mut self, v: &[&str]) -> &mut Claim {
+        self.registered.aud = Some(Audience::Multiple(v.iter().map(|s| s.to_string()).collect()));
         self
     }
     pub fn set_sub(&mut self, v: &str) -> &mut Claim {
@@ -82,54 +103,89 @@ impl Claim {
         self.payload.get(&key.to_string())
     }
 
+    /// whether a registered or private claim named `key` is present
+    pub fn has_claim(&self, key: &str) -> bool {
+        let reg = &self.registered;
+        match key {
+            "iss" => reg.iss.is_some(),
+            "sub" => reg.sub.is_some(),
+            "exp" => reg.exp.is_some(),
+            "nbf" => reg.nbf.is_some(),
+            "iat" => reg.iat.is_some(),
+            "aud" => reg.aud.is_some(),
+            "jti" => reg.jti.is_some(),
+            _ => self.payload.contains_key(key),
+        }
+    }
+
+}
+
+// a NumericDate of RFC 7519, seconds that may be fractional, rounded to the
+// stricter side: down for `exp`, up for `nbf` and `iat`, so the claim never
+// holds for longer than it says; negative dates and dates past u64 are refused
+fn numeric_date(value: Option<Value>, name: &str, round_up: bool) -> Result<Option<u64>> {
+    let invalid = || Error::InvalidClaim(name.to_string());
+    let n = match value {
+        Some(Value::Number(n)) => n,
+        Some(Value::Null) | None => return Ok(None),
+        _ => return Err(invalid()),
+    };
+    if let Some(secs) = n.as_u64() {
+        return Ok(Some(secs));
+    }
+    let secs = n.as_f64().ok_or_else(invalid)?;
+    let secs = if round_up { secs.ceil() } else { secs.floor() };
+    // `u64::MAX as f64` is 2^64, the first float past u64::MAX
+    if !(secs >= 0.0 && secs < u64::MAX as f64) {
+        return Err(invalid());
+    }
+    Ok(Some(secs as u64))
 }
 
 impl JWTStringConvertable for Claim {
     fn from_base64_str(string: &str) -> Result<Claim> {
-        let slice = try!(decode_config(string, URL_SAFE));
-        let obj: Value = try!(serde_json::from_slice(&slice));
+        let slice = base64_decode(string)?;
+        let obj: Value = serde_json::from_slice(&slice)?;
         let mut map = match obj {
             Value::Object(map) => map,
-            _ => return Err(ErrorKind::InvalidFormat.into())
+            _ => return Err(Error::MalformedSegment {index: 1})
         };
         // dispatch every items to 
         let mut claim = Claim::default();
         {
             let reg: &mut RegisteredClaim = &mut claim.registered;
-            reg.exp = match map.remove("exp") {
-                Some(Value::Number(u)) => u.as_u64(),
-                Some(Value::Null) | None => None,
-                _ => return Err(ErrorKind::InvalidFormat.into()),
-            };
-            reg.nbf = match map.remove("nbf") {
-                Some(Value::Number(u)) => u.as_u64(),
-                Some(Value::Null) | None => None,
-                _ => return Err(ErrorKind::InvalidFormat.into()),
-            };
-            reg.iat = match map.remove("iat") {
-                Some(Value::Number(u)) => u.as_u64(),
-                Some(Value::Null) | None => None,
-                _ => return Err(ErrorKind::InvalidFormat.into()),
-            };
+            reg.exp = numeric_date(map.remove("exp"), "exp", false)?;
+            reg.nbf = numeric_date(map.remove("nbf"), "nbf", true)?;
+            reg.iat = numeric_date(map.remove("iat"), "iat", true)?;
             reg.iss = match map.remove("iss") {
                 Some(Value::String(u)) => Some(u),
                 Some(Value::Null) | None => None,
-                _ => return Err(ErrorKind::InvalidFormat.into()),
+                _ => return Err(Error::InvalidClaim("iss".to_string())),
             };
             reg.aud = match map.remove("aud") {
-                Some(Value::String(u)) => Some(u),
+                Some(Value::String(u)) => Some(Audience::Single(u)),
+                Some(Value::Array(vec)) => {
+                    let mut audiences = Vec::new();
+                    for v in vec {
+                        match v {
+                            Value::String(u) => audiences.push(u),
+                            _ => return Err(Error::InvalidClaim("aud".to_string())),
+                        }
+                    }
+                    Some(Audience::Multiple(audiences))
+                }
                 Some(Value::Null) | None => None,
-                _ => return Err(ErrorKind::InvalidFormat.into()),
+                _ => return Err(Error::InvalidClaim("aud".to_string())),
             };
             reg.sub = match map.remove("sub") {
                 Some(Value::String(u)) => Some(u),
                 Some(Value::Null) | None => None,
-                _ => return Err(ErrorKind::InvalidFormat.into()),
+                _ => return Err(Error::InvalidClaim("sub".to_string())),
             };
             reg.jti = match map.remove("jti") {
                 Some(Value::String(u)) => Some(u),
                 Some(Value::Null) | None => None,
-                _ => return Err(ErrorKind::InvalidFormat.into()),
+                _ => return Err(Error::InvalidClaim("jti".to_string())),
             };
         }
         claim.payload = map;
@@ -139,32 +195,36 @@ impl JWTStringConvertable for Claim {
     fn to_base64_str(&self) -> Result<String> {
         let mut map: Map<String, Value> = self.payload.clone();
         if let Some(v) = self.registered.exp {
-            let value = try!(to_value(v));
+            let value = to_value(v)?;
             map.insert("exp".to_string(), value);
         };
         if let Some(ref v) = self.registered.nbf {
-            let value = try!(to_value(v));
+            let value = to_value(v)?;
             map.insert("nbf".to_string(), value);
         };
         if let Some(ref v) = self.registered.iat {
-            let value = try!(to_value(v));
+            let value = to_value(v)?;
             map.insert("iat".to_string(), value);
         };
         if let Some(ref v) = self.registered.iss {
-            let value = try!(to_value(v));
+            let value = to_value(v)?;
             map.insert("iss".to_string(), value);
         };
         if let Some(ref v) = self.registered.aud {
-            let value = try!(to_value(v));
+            let value = to_value(v)?;
             map.insert("aud".to_string(), value);
         };
         if let Some(ref v) = self.registered.sub {
-            let value = try!(to_value(v));
+            let value = to_value(v)?;
             map.insert("sub".to_string(), value);
         };
+        if let Some(ref v) = self.registered.jti {
+            let value = to_value(v)?;
+            map.insert("jti".to_string(), value);
+        };
 
-        let b_string = try!(serde_json::to_vec(&map));
-        Ok(encode_config(&b_string, URL_SAFE))
+        let b_string = serde_json::to_vec(&map)?;
+        Ok(base64_encode(&b_string))
     }
 }
 
@@ -0,0 +1,129 @@
+//! the few DER (X.690) encodings JOSE needs: SEQUENCE and non-negative INTEGER
+//! for ECDSA signatures, and the strings and OIDs inside PKCS#1, SEC1, PKCS#8
+//! and SubjectPublicKeyInfo keys
+//!
+//! decoding is strict, anything that is not the one minimal encoding of a
+//! value gives `None`, never a panic
+
+pub const TAG_INTEGER: u8 = 0x02;
+pub const TAG_BIT_STRING: u8 = 0x03;
+pub const TAG_OCTET_STRING: u8 = 0x04;
+pub const TAG_NULL: u8 = 0x05;
+pub const TAG_OID: u8 = 0x06;
+pub const TAG_SEQUENCE: u8 = 0x30;
+
+/// the tag of the context specific, constructed `[n]`
+pub fn tag_context(n: u8) -> u8 {
+    0xa0 | n
+}
+
+// lengths above this are never needed here, and keep the arithmetic in range
+const MAX_LENGTH_BYTES: usize = 4;
+
+// only the openssl backend writes DER, the ECDSA signatures it wants
+
+/// append the definite length of `len`, short form below 128
+#[cfg(feature = "openssl")]
+pub fn encode_length(len: usize, out: &mut Vec<u8>) {
+    if len < 0x80 {
+        out.push(len as u8);
+        return;
+    }
+    let bytes = (len as u64).to_be_bytes();
+    let skip = bytes.iter().take_while(|&&b| b == 0).count();
+    out.push(0x80 | (bytes.len() - skip) as u8);
+    out.extend_from_slice(&bytes[skip..]);
+}
+
+/// append a tag, its length and `content`
+#[cfg(feature = "openssl")]
+pub fn encode_tlv(tag: u8, content: &[u8], out: &mut Vec<u8>) {
+    out.push(tag);
+    encode_length(content.len(), out);
+    out.extend_from_slice(content);
+}
+
+/// append the INTEGER of the unsigned big-endian `magnitude`, leading zeros
+/// are dropped and one is added back when the high bit is set
+#[cfg(feature = "openssl")]
+pub fn encode_unsigned_integer(magnitude: &[u8], out: &mut Vec<u8>) {
+    let skip = magnitude.iter().take_while(|&&b| b == 0).count();
+    let magnitude = &magnitude[skip..];
+    let mut content = Vec::with_capacity(magnitude.len() + 1);
+    match magnitude.first() {
+        None => content.push(0),
+        Some(&b) if b & 0x80 != 0 => content.push(0),
+        Some(_) => {}
+    }
+    content.extend_from_slice(magnitude);
+    encode_tlv(TAG_INTEGER, &content, out);
+}
+
+/// split a `tag` element off the front of `input`, giving its content and
+/// the bytes after it
+pub fn decode_tlv(tag: u8, input: &[u8]) -> Option<(&[u8], &[u8])> {
+    let (&actual, rest) = input.split_first()?;
+    if actual != tag {
+        return None;
+    }
+    let (len, rest) = decode_length(rest)?;
+    if rest.len() < len {
+        return None;
+    }
+    Some(rest.split_at(len))
+}
+
+/// like `decode_tlv`, but a missing element or one of another tag gives
+/// `Some((None, input))`
+pub fn decode_optional_tlv(tag: u8, input: &[u8]) -> Option<(Option<&[u8]>, &[u8])> {
+    match input.first() {
+        Some(&actual) if actual == tag => {
+            let (content, rest) = decode_tlv(tag, input)?;
+            Some((Some(content), rest))
+        }
+        _ => Some((None, input)),
+    }
+}
+
+fn decode_length(input: &[u8]) -> Option<(usize, &[u8])> {
+    let (&first, rest) = input.split_first()?;
+    if first < 0x80 {
+        return Some((first as usize, rest));
+    }
+    // 0x80 is the indefinite length of BER, not allowed in DER
+    let count = (first & 0x7f) as usize;
+    if count == 0 || count > MAX_LENGTH_BYTES || rest.len() < count {
+        return None;
+    }
+    let (bytes, rest) = rest.split_at(count);
+    // minimal: no leading zero byte, and the long form only from 128 on
+    if bytes[0] == 0 {
+        return None;
+    }
+    let len = bytes.iter().fold(0usize, |acc, &b| (acc << 8) | b as usize);
+    if len < 0x80 {
+        return None;
+    }
+    Some((len, rest))
+}
+
+/// the magnitude of a non-negative INTEGER content, without the sign byte
+pub fn decode_unsigned_integer(content: &[u8]) -> Option<&[u8]> {
+    match content {
+        [] => None,
+        // negative
+        [first, ..] if first & 0x80 != 0 => None,
+        // a leading zero is only allowed in front of a high bit
+        [0, second, ..] if second & 0x80 == 0 => None,
+        [0, rest @ ..] if !rest.is_empty() => Some(rest),
+        _ => Some(content),
+    }
+}
+
+/// the content of a BIT STRING, which must not have unused bits
+pub fn decode_bit_string(content: &[u8]) -> Option<&[u8]> {
+    match content {
+        [0, rest @ ..] => Some(rest),
+        _ => None,
+    }
+}
@@ -1,26 +1,128 @@
+use std::error;
+use std::fmt;
+use std::result;
+
 use serde_json;
 use base64;
+#[cfg(feature = "openssl")]
 use openssl;
 
-error_chain! {
-    links {}
-    foreign_links {
-        JsonError(serde_json::Error);
-        Base64Error(base64::DecodeError);
-        CryptoFailure(openssl::error::ErrorStack);
-    }
-    errors {
-        UnsupportAlgorithm {
-            description("unsupport algorithm")
-            display("unsupport algorithm")
-        }
-        InvalidFormat {
-            description("invalid format")
-            display("invalid format")
+use super::header::Algorithm;
+use super::jwe::{KeyManagement, ContentEncryption};
+
+/// every way encoding or decoding a token can fail
+///
+/// the enum is matched exhaustively on purpose, e.g. to map each failure to
+/// an HTTP response, so a new variant is a breaking change
+#[derive(Debug)]
+pub enum Error {
+    /// the algorithm can not be used for this operation
+    UnsupportedAlgorithm,
+    /// the token does not have a segment at `index`, or has one too many,
+    /// or segment `index` does not hold the expected JSON object
+    MalformedSegment { index: usize },
+    /// a segment, or a parameter of a jwk, is not unpadded base64url
+    InvalidBase64(base64::DecodeError),
+    /// a header, payload or jwk is not the expected JSON
+    InvalidJson(serde_json::Error),
+    /// a registered claim has the wrong JSON type
+    InvalidClaim(String),
+    InvalidSignature,
+    /// the JWE ciphertext, its tag or its protected header was altered, or
+    /// the key is not the one it was encrypted for
+    DecryptionFailed,
+    /// `alg` of the header is not in `Validation.algorithms`
+    AlgorithmNotAllowed(Algorithm),
+    /// `alg` of the JWE header is not in `JweValidation.algorithms`
+    KeyManagementNotAllowed(KeyManagement),
+    /// `enc` of the JWE header is not in `JweValidation.encryptions`
+    EncryptionNotAllowed(ContentEncryption),
+    /// the key belongs to another family of algorithms than `alg`
+    KeyFamilyMismatch,
+    /// the curve of the key does not match `alg`
+    KeyAlgorithmMismatch(Algorithm),
+    WeakHmacSecret { len: usize, required: usize },
+    WeakRsaKey { bits: u32, required: u32 },
+    InvalidJwk(String),
+    /// a PEM or DER key that can not be read, or whose parts do not match
+    InvalidKey(String),
+    MissingKid,
+    UnknownKid(String),
+    Expired,
+    Immature,
+    InvalidIssuedAt,
+    InvalidAudience,
+    MissingRequiredClaim(String),
+    /// `alg` is `none`, which only `decode_unsecured` accepts
+    UnsecuredToken,
+    /// the crypto backend failed, the source is the error of its library
+    Crypto(Box<dyn error::Error + Send + Sync>),
+}
+
+pub type Result<T> = result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::UnsupportedAlgorithm => write!(f, "unsupported algorithm"),
+            Error::MalformedSegment { index } => write!(f, "malformed token at segment {}", index),
+            Error::InvalidBase64(ref err) => write!(f, "invalid base64: {}", err),
+            Error::InvalidJson(ref err) => write!(f, "invalid json: {}", err),
+            Error::InvalidClaim(ref claim) => write!(f, "invalid claim: '{}'", claim),
+            Error::InvalidSignature => write!(f, "invalid signature"),
+            Error::DecryptionFailed => write!(f, "decryption failed"),
+            Error::AlgorithmNotAllowed(alg) => write!(f, "algorithm not allowed: {:?}", alg),
+            Error::KeyManagementNotAllowed(alg) => write!(f, "key management not allowed: {:?}", alg),
+            Error::EncryptionNotAllowed(enc) => write!(f, "content encryption not allowed: {:?}", enc),
+            Error::KeyFamilyMismatch => write!(f, "key can not be used with this algorithm"),
+            Error::KeyAlgorithmMismatch(alg) =>
+                write!(f, "the curve of the key does not match the algorithm: {:?}", alg),
+            Error::WeakHmacSecret { len, required } =>
+                write!(f, "HMAC secret too short: {} bytes, at least {} required", len, required),
+            Error::WeakRsaKey { bits, required } =>
+                write!(f, "RSA key too small: {} bits, at least {} required", bits, required),
+            Error::InvalidJwk(ref reason) => write!(f, "invalid jwk: {}", reason),
+            Error::InvalidKey(ref reason) => write!(f, "invalid key: {}", reason),
+            Error::MissingKid => write!(f, "missing kid in header"),
+            Error::UnknownKid(ref kid) => write!(f, "no key matches the kid: '{}'", kid),
+            Error::Expired => write!(f, "expired signature"),
+            Error::Immature => write!(f, "immature signature"),
+            Error::InvalidIssuedAt => write!(f, "invalid issued at"),
+            Error::InvalidAudience => write!(f, "invalid audience"),
+            Error::MissingRequiredClaim(ref claim) => write!(f, "missing required claim: '{}'", claim),
+            Error::UnsecuredToken =>
+                write!(f, "unsecured token, alg 'none' is only accepted by decode_unsecured"),
+            Error::Crypto(ref err) => write!(f, "crypto failure: {}", err),
         }
-        InvalidSignature {
-            description("invalid signature")
-            display("invalid signature")
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::InvalidBase64(ref err) => Some(err),
+            Error::InvalidJson(ref err) => Some(err),
+            Error::Crypto(ref err) => Some(&**err),
+            _ => None,
         }
     }
 }
+
+impl From<base64::DecodeError> for Error {
+    fn from(err: base64::DecodeError) -> Error {
+        Error::InvalidBase64(err)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Error {
+        Error::InvalidJson(err)
+    }
+}
+
+#[cfg(feature = "openssl")]
+impl From<openssl::error::ErrorStack> for Error {
+    fn from(err: openssl::error::ErrorStack) -> Error {
+        Error::Crypto(Box::new(err))
+    }
+}
@@ -1,33 +1,164 @@
 use std::default::Default;
+use serde_json;
+use serde_json::value::{Map, Value};
 
-#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+use super::errors::*;
+use super::jwk::Jwk;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
 pub enum Algorithm {
+    #[default]
     HS256,
     HS384,
     HS512,
     RS256,
     RS384,
     RS512,
+    PS256,
+    PS384,
+    PS512,
     ES256,
     ES384,
     ES512,
+    ES256K,
+    EdDSA,
+    /// an unsecured token without signature, only produced by
+    /// `encode_unsecured` and only accepted by `decode_unsecured`
+    #[serde(rename = "none")]
+    None,
+}
+
+/// the kind of key an `Algorithm` signs and verifies with
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AlgorithmFamily {
+    Hmac,
+    Rsa,
+    Ec,
+    Ed,
+    /// `Algorithm::None`, no key belongs to it
+    Unsecured,
 }
 
-#[derive(Debug, PartialEq, Serialize, Deserialize)]
+/// # JWT Header
+/// the JOSE header of RFC 7515, parameters this crate does not know about
+/// are kept in `extra`
+///
+/// # Example
+/// ```
+/// use simple_jwt::{encode_with_header, EncodingKey, Header, Claim, Algorithm};
+///
+/// let mut header = Header::new(Algorithm::HS256);
+/// header.kid = Some("2017-01".to_string());
+/// header.extra.insert("tenant".to_string(), "realli".into());
+///
+/// let key = EncodingKey::from_secret(b"a secret of at least thirty-two bytes").unwrap();
+/// let result = encode_with_header(&Claim::default(), &key, &header).unwrap();
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Header {
     pub alg: Algorithm,
-    pub typ: String
+    /// optional in RFC 7515, `Header::new` sets it to "JWT"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub typ: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cty: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kid: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jku: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jwk: Option<Jwk>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub x5u: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub x5c: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub x5t: Option<String>,
+    #[serde(rename = "x5t#S256", default, skip_serializing_if = "Option::is_none")]
+    pub x5t_s256: Option<String>,
+    /// extensions that must be understood to accept the token, none is, so
+    /// decoding fails whenever it is set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub crit: Option<Vec<String>>,
+    /// private header parameters
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
 }
 
-impl Default for Algorithm {
-    fn default() -> Algorithm {
-        Algorithm::HS256
+impl Algorithm {
+    pub fn family(&self) -> AlgorithmFamily {
+        match *self {
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => AlgorithmFamily::Hmac,
+            Algorithm::RS256 | Algorithm::RS384 | Algorithm::RS512
+                | Algorithm::PS256 | Algorithm::PS384 | Algorithm::PS512 => AlgorithmFamily::Rsa,
+            Algorithm::ES256 | Algorithm::ES384 | Algorithm::ES512
+                | Algorithm::ES256K => AlgorithmFamily::Ec,
+            Algorithm::EdDSA => AlgorithmFamily::Ed,
+            Algorithm::None => AlgorithmFamily::Unsecured,
+        }
     }
 }
 
 impl Header {
     pub fn new(alg: Algorithm) -> Header {
-        Header {alg: alg, typ: "JWT".to_string()}
+        Header {
+            alg,
+            typ: Some("JWT".to_string()),
+            cty: None,
+            kid: None,
+            jku: None,
+            jwk: None,
+            x5u: None,
+            x5c: None,
+            x5t: None,
+            x5t_s256: None,
+            crit: None,
+            extra: Map::new(),
+        }
+    }
+}
+
+/// the parameters `Token::verify` checks, read from whatever type the header
+/// of a `Token` is parsed into
+pub trait JoseHeader {
+    /// `alg`, an error when it is missing or unknown
+    fn alg(&self) -> Result<Algorithm>;
+    fn kid(&self) -> Option<&str>;
+    /// the names listed in `crit`, `None` when there is no `crit`
+    fn crit(&self) -> Option<Vec<&str>>;
+}
+
+impl JoseHeader for Header {
+    fn alg(&self) -> Result<Algorithm> {
+        Ok(self.alg)
+    }
+
+    fn kid(&self) -> Option<&str> {
+        self.kid.as_ref().map(|kid| &kid[..])
+    }
+
+    fn crit(&self) -> Option<Vec<&str>> {
+        self.crit.as_ref().map(|crit| crit.iter().map(|name| &name[..]).collect())
     }
 }
 
+// a header kept as raw JSON
+impl JoseHeader for Value {
+    fn alg(&self) -> Result<Algorithm> {
+        match self.get("alg") {
+            Some(alg) => Ok(serde_json::from_value(alg.clone())?),
+            None => Err(Error::MalformedSegment {index: 0}),
+        }
+    }
+
+    fn kid(&self) -> Option<&str> {
+        self.get("kid").and_then(Value::as_str)
+    }
+
+    fn crit(&self) -> Option<Vec<&str>> {
+        self.get("crit").map(|crit| match crit.as_array() {
+            Some(names) => names.iter().filter_map(Value::as_str).collect(),
+            None => Vec::new(),
+        })
+    }
+}
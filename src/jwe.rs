@@ -0,0 +1,712 @@
+use std::fmt;
+use std::sync::Arc;
+use serde::Serialize;
+use serde_json::value::{Map, Value, to_value};
+
+use super::errors::*;
+use super::header::{Algorithm, AlgorithmFamily};
+use super::material::KeyMaterial;
+use super::jwk::Jwk;
+use super::key::{KeyPolicy, check_material, private_material, public_material};
+use super::backend;
+use super::utils::{JWTStringConvertable, base64_encode, base64_decode, constant_time_eq};
+
+/// how the content encryption key reaches the recipient, the `alg` of a
+/// JWE header
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum KeyManagement {
+    /// the shared key is the content encryption key, the encrypted key is empty
+    #[serde(rename = "dir")]
+    Dir,
+    /// a random content encryption key, encrypted to the RSA key of the
+    /// recipient with RSAES-OAEP and SHA-1, only with the `openssl` backend
+    /// as the `rsa` crate of `rust-crypto` is open to the Marvin attack
+    /// (RUSTSEC-2023-0071)
+    #[serde(rename = "RSA-OAEP")]
+    RSA_OAEP,
+    /// RSAES-OAEP with SHA-256, only with `openssl` too
+    #[serde(rename = "RSA-OAEP-256")]
+    RSA_OAEP_256,
+    /// a random content encryption key, wrapped with AES-128 Key Wrap by the
+    /// shared key
+    A128KW,
+    /// AES-192 Key Wrap with the shared key
+    A192KW,
+    /// AES-256 Key Wrap with the shared key
+    A256KW,
+    /// a random content encryption key, encrypted with AES-128-GCM by the
+    /// shared key, its IV and tag are sent as `iv` and `tag`
+    A128GCMKW,
+    /// AES-192-GCM with the shared key
+    A192GCMKW,
+    /// AES-256-GCM with the shared key
+    A256GCMKW,
+    /// ECDH between an ephemeral key, sent as `epk`, and the EC key of the
+    /// recipient, the Concat KDF of the shared secret is the content
+    /// encryption key, the encrypted key is empty
+    #[serde(rename = "ECDH-ES")]
+    ECDH_ES,
+    /// ECDH-ES, the derived key wraps a random content encryption key with
+    /// AES-128 Key Wrap
+    #[serde(rename = "ECDH-ES+A128KW")]
+    ECDH_ES_A128KW,
+    /// ECDH-ES with AES-192 Key Wrap
+    #[serde(rename = "ECDH-ES+A192KW")]
+    ECDH_ES_A192KW,
+    /// ECDH-ES with AES-256 Key Wrap
+    #[serde(rename = "ECDH-ES+A256KW")]
+    ECDH_ES_A256KW,
+}
+
+impl KeyManagement {
+    fn is_ecdh(&self) -> bool {
+        matches!(*self, KeyManagement::ECDH_ES
+                        | KeyManagement::ECDH_ES_A128KW
+                        | KeyManagement::ECDH_ES_A192KW
+                        | KeyManagement::ECDH_ES_A256KW)
+    }
+
+    // the length of the AES key that wraps the content encryption key, the
+    // shared one or the one ECDH-ES derives
+    fn kek_len(&self) -> Option<usize> {
+        match *self {
+            KeyManagement::A128KW
+                | KeyManagement::A128GCMKW
+                | KeyManagement::ECDH_ES_A128KW => Some(16),
+            KeyManagement::A192KW
+                | KeyManagement::A192GCMKW
+                | KeyManagement::ECDH_ES_A192KW => Some(24),
+            KeyManagement::A256KW
+                | KeyManagement::A256GCMKW
+                | KeyManagement::ECDH_ES_A256KW => Some(32),
+            _ => None,
+        }
+    }
+}
+
+/// how the claims are encrypted, the `enc` of a JWE header
+#[allow(non_camel_case_types)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ContentEncryption {
+    /// AES-128-CBC, authenticated by HMAC-SHA-256 truncated to 128 bits
+    #[serde(rename = "A128CBC-HS256")]
+    A128CBC_HS256,
+    /// AES-192-CBC, authenticated by HMAC-SHA-384 truncated to 192 bits
+    #[serde(rename = "A192CBC-HS384")]
+    A192CBC_HS384,
+    /// AES-256-CBC, authenticated by HMAC-SHA-512 truncated to 256 bits
+    #[serde(rename = "A256CBC-HS512")]
+    A256CBC_HS512,
+    A128GCM,
+    A192GCM,
+    A256GCM,
+}
+
+impl ContentEncryption {
+    /// the length of the content encryption key in bytes, for the CBC ones
+    /// the HMAC key and the AES key put together
+    pub fn key_len(&self) -> usize {
+        match *self {
+            ContentEncryption::A128GCM => 16,
+            ContentEncryption::A192GCM => 24,
+            ContentEncryption::A256GCM | ContentEncryption::A128CBC_HS256 => 32,
+            ContentEncryption::A192CBC_HS384 => 48,
+            ContentEncryption::A256CBC_HS512 => 64,
+        }
+    }
+
+    fn iv_len(&self) -> usize {
+        match self.hmac() {
+            Some(_) => 16,
+            None => 12,
+        }
+    }
+
+    // the GCM tag is 128 bits, the CBC one half of the CEK
+    fn tag_len(&self) -> usize {
+        match self.hmac() {
+            Some(_) => self.key_len() / 2,
+            None => GCM_TAG_LEN,
+        }
+    }
+
+    // the HMAC of the CBC ones, `None` for GCM
+    fn hmac(&self) -> Option<Algorithm> {
+        match *self {
+            ContentEncryption::A128CBC_HS256 => Some(Algorithm::HS256),
+            ContentEncryption::A192CBC_HS384 => Some(Algorithm::HS384),
+            ContentEncryption::A256CBC_HS512 => Some(Algorithm::HS512),
+            _ => None,
+        }
+    }
+}
+
+/// # JWE Header
+/// the protected header of RFC 7516, parameters this crate does not know
+/// about are kept in `extra`, `decrypt` rejects tokens with `crit` or `zip`
+///
+/// # Example
+/// ```
+/// use simple_jwt::{JweHeader, KeyManagement, ContentEncryption};
+///
+/// let mut header = JweHeader::new(KeyManagement::Dir, ContentEncryption::A256GCM);
+/// header.kid = Some("2017-01".to_string());
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JweHeader {
+    pub alg: KeyManagement,
+    pub enc: ContentEncryption,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub typ: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cty: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kid: Option<String>,
+    /// the public ephemeral key of ECDH-ES, set by `encrypt`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub epk: Option<Jwk>,
+    /// base64url information about the sender for the ECDH-ES key derivation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub apu: Option<String>,
+    /// base64url information about the recipient for the ECDH-ES key derivation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub apv: Option<String>,
+    /// the base64url IV that encrypted the content encryption key with
+    /// AES-GCM, set by `encrypt`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iv: Option<String>,
+    /// the base64url tag of the content encryption key encrypted with AES-GCM,
+    /// set by `encrypt`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    /// private header parameters
+    #[serde(flatten)]
+    pub extra: Map<String, Value>,
+}
+
+impl JweHeader {
+    pub fn new(alg: KeyManagement, enc: ContentEncryption) -> JweHeader {
+        JweHeader {
+            alg,
+            enc,
+            typ: Some("JWT".to_string()),
+            cty: None,
+            kid: None,
+            epk: None,
+            apu: None,
+            apv: None,
+            iv: None,
+            tag: None,
+            extra: Map::new(),
+        }
+    }
+}
+
+/// # JweValidation
+/// the key managements and content encryptions `decrypt` accepts, a token
+/// with any other `alg` or `enc` is rejected before the key is used, so a
+/// key meant for one algorithm is never used with another
+///
+/// # Example
+/// ```
+/// use simple_jwt::{JweValidation, KeyManagement, ContentEncryption};
+///
+/// let mut validation = JweValidation::new(KeyManagement::A256KW, ContentEncryption::A256GCM);
+/// validation.encryptions.push(ContentEncryption::A256CBC_HS512);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct JweValidation {
+    /// the `alg` a token may have
+    pub algorithms: Vec<KeyManagement>,
+    /// the `enc` a token may have
+    pub encryptions: Vec<ContentEncryption>,
+}
+
+impl JweValidation {
+    /// only allow `alg` with `enc`
+    pub fn new(alg: KeyManagement, enc: ContentEncryption) -> JweValidation {
+        JweValidation {
+            algorithms: vec![alg],
+            encryptions: vec![enc],
+        }
+    }
+}
+
+/// # EncryptionKey
+/// a parsed key used to encrypt tokens to their recipient
+///
+/// # Example
+/// ```
+/// use simple_jwt::{encrypt, EncryptionKey, JweHeader, KeyManagement, ContentEncryption, Claim};
+///
+/// let key = EncryptionKey::from_secret(b"a 256 bit key for A256GCM tokens").unwrap();
+/// let header = JweHeader::new(KeyManagement::Dir, ContentEncryption::A256GCM);
+/// let result = encrypt(&Claim::default(), &key, &header).unwrap();
+/// ```
+#[derive(Clone)]
+pub struct EncryptionKey {
+    material: Arc<KeyMaterial>,
+}
+
+/// # DecryptionKey
+/// a parsed key used to decrypt tokens sent to its holder
+#[derive(Clone)]
+pub struct DecryptionKey {
+    material: Arc<KeyMaterial>,
+}
+
+fn secret_material(secret: &[u8]) -> Result<KeyMaterial> {
+    if secret.is_empty() {
+        return Err(Error::InvalidKey("the key is empty".to_string()));
+    }
+    Ok(KeyMaterial::Secret(secret.to_vec()))
+}
+
+// the same checks as for signing keys, RSA moduli of at least 2048 bits and
+// EC points on their curve
+fn checked_material(material: KeyMaterial, family: AlgorithmFamily) -> Result<KeyMaterial> {
+    check_material(&material, family, &KeyPolicy::default())?;
+    Ok(material)
+}
+
+impl EncryptionKey {
+    /// a symmetric key shared with the recipient, for `dir` the content
+    /// encryption key itself, as long as `enc` wants it, for the AES key
+    /// wraps as long as `alg` wants it
+    pub fn from_secret(secret: &[u8]) -> Result<EncryptionKey> {
+        Ok(EncryptionKey {material: Arc::new(secret_material(secret)?)})
+    }
+
+    /// RSA public key of the recipient in PEM format (`BEGIN PUBLIC KEY`)
+    /// for RSA-OAEP and RSA-OAEP-256, the modulus must have at least 2048 bits
+    pub fn from_rsa_pem(pem: &[u8]) -> Result<EncryptionKey> {
+        let material = public_material(KeyMaterial::from_pem(pem)?)?;
+        Ok(EncryptionKey {material: Arc::new(checked_material(material, AlgorithmFamily::Rsa)?)})
+    }
+
+    /// EC public key of the recipient in PEM format (`BEGIN PUBLIC KEY`) for
+    /// ECDH-ES and its key wrap variants, on P-256, P-384 or P-521
+    pub fn from_ec_pem(pem: &[u8]) -> Result<EncryptionKey> {
+        let material = public_material(KeyMaterial::from_pem(pem)?)?;
+        Ok(EncryptionKey {material: Arc::new(checked_material(material, AlgorithmFamily::Ec)?)})
+    }
+
+    /// RSA or EC JWK of the recipient, only its public parameters are used
+    pub fn from_jwk(jwk: &Jwk) -> Result<EncryptionKey> {
+        let material = jwk.key_material(false)?;
+        let family = material.family();
+        Ok(EncryptionKey {material: Arc::new(checked_material(material, family)?)})
+    }
+}
+
+impl DecryptionKey {
+    /// a symmetric key shared with the sender, for `dir` the content
+    /// encryption key itself, as long as `enc` wants it, for the AES key
+    /// wraps as long as `alg` wants it
+    pub fn from_secret(secret: &[u8]) -> Result<DecryptionKey> {
+        Ok(DecryptionKey {material: Arc::new(secret_material(secret)?)})
+    }
+
+    /// RSA private key in PEM format, PKCS#1 or PKCS#8, for RSA-OAEP and
+    /// RSA-OAEP-256, the modulus must have at least 2048 bits
+    pub fn from_rsa_pem(pem: &[u8]) -> Result<DecryptionKey> {
+        let material = private_material(KeyMaterial::from_pem(pem)?)?;
+        Ok(DecryptionKey {material: Arc::new(checked_material(material, AlgorithmFamily::Rsa)?)})
+    }
+
+    /// EC private key in PEM format, SEC1 or PKCS#8, for ECDH-ES and its key
+    /// wrap variants, on P-256, P-384 or P-521
+    pub fn from_ec_pem(pem: &[u8]) -> Result<DecryptionKey> {
+        let material = private_material(KeyMaterial::from_pem(pem)?)?;
+        Ok(DecryptionKey {material: Arc::new(checked_material(material, AlgorithmFamily::Ec)?)})
+    }
+
+    /// RSA or EC JWK with its private parameters
+    pub fn from_jwk(jwk: &Jwk) -> Result<DecryptionKey> {
+        let material = jwk.key_material(true)?;
+        let family = material.family();
+        Ok(DecryptionKey {material: Arc::new(checked_material(material, family)?)})
+    }
+}
+
+// never print the key material
+impl fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("EncryptionKey").field("family", &self.material.family()).finish()
+    }
+}
+
+impl fmt::Debug for DecryptionKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DecryptionKey").field("family", &self.material.family()).finish()
+    }
+}
+
+fn wrong_key_len(enc: ContentEncryption) -> Error {
+    Error::InvalidKey(format!("{:?} needs a key of {} bytes", enc, enc.key_len()))
+}
+
+// the shared key of the AES key wraps must have the length of `alg`
+fn check_kek(alg: KeyManagement, kek: &[u8]) -> Result<()> {
+    match alg.kek_len() {
+        Some(len) if len == kek.len() => Ok(()),
+        len => Err(Error::InvalidKey(format!("{:?} needs a key of {} bytes", alg, len.unwrap_or(0)))),
+    }
+}
+
+// RFC 7518 4.7.1, AES-GCM key wrapping always uses 96 bit IVs
+const GCMKW_IV_LEN: usize = 12;
+
+// and 128 bit tags, like the content encryption
+const GCM_TAG_LEN: usize = 16;
+
+// the name of `alg` or `enc` in the header
+fn name<T: Serialize>(value: &T) -> Result<String> {
+    match to_value(value)? {
+        Value::String(name) => Ok(name),
+        _ => Err(Error::UnsupportedAlgorithm),
+    }
+}
+
+fn optional_base64(field: &Option<String>) -> Result<Vec<u8>> {
+    match *field {
+        Some(ref value) => base64_decode(value),
+        None => Ok(Vec::new()),
+    }
+}
+
+// a header parameter the key management of the token needs
+fn required_param(field: &Option<String>) -> Result<Vec<u8>> {
+    match *field {
+        Some(ref value) => base64_decode(value),
+        None => Err(Error::MalformedSegment {index: 0}),
+    }
+}
+
+// RFC 7518 4.6.2, the Concat KDF of NIST SP 800-56A with SHA-256, `len`
+// bytes derived from the shared secret `z`
+fn concat_kdf(z: &[u8], algorithm_id: &str, apu: &[u8], apv: &[u8], len: usize) -> Result<Vec<u8>> {
+    let mut other_info = Vec::new();
+    for field in [algorithm_id.as_bytes(), apu, apv].iter() {
+        other_info.extend_from_slice(&(field.len() as u32).to_be_bytes());
+        other_info.extend_from_slice(field);
+    }
+    other_info.extend_from_slice(&((len * 8) as u32).to_be_bytes());
+
+    let mut key = Vec::with_capacity(len + 32);
+    let mut counter = 1u32;
+    while key.len() < len {
+        let mut input = counter.to_be_bytes().to_vec();
+        input.extend_from_slice(z);
+        input.extend_from_slice(&other_info);
+        key.extend_from_slice(&backend::sha256(&input)?);
+        counter += 1;
+    }
+    key.truncate(len);
+    Ok(key)
+}
+
+// the key ECDH-ES derives, the content encryption key itself, or for the key
+// wrap variants the key that wraps it
+fn ecdh_derived_key(header: &JweHeader, z: &[u8]) -> Result<Vec<u8>> {
+    let (algorithm_id, len) = match header.alg.kek_len() {
+        Some(len) => (name(&header.alg)?, len),
+        None => (name(&header.enc)?, header.enc.key_len()),
+    };
+    let apu = optional_base64(&header.apu)?;
+    let apv = optional_base64(&header.apv)?;
+    concat_kdf(z, &algorithm_id, &apu, &apv, len)
+}
+
+// the public key of the sender, which must be on the curve of `key`: ECDH
+// with a point of another, weaker, curve would leak the private key, the
+// backends reject points off the curve
+fn ephemeral_key(header: &JweHeader, key: &KeyMaterial) -> Result<KeyMaterial> {
+    let epk = match header.epk {
+        Some(ref epk) => epk.ec_public_material()?,
+        None => return Err(Error::MalformedSegment {index: 0}),
+    };
+    match (&epk, key) {
+        (&KeyMaterial::Ec {curve: epk_curve, ..}, &KeyMaterial::Ec {curve, ..}) if epk_curve == curve => {}
+        _ => return Err(Error::InvalidKey("the ephemeral key is not on the curve of the key".to_string())),
+    }
+    Ok(epk)
+}
+
+// the content encryption key and the encrypted key of the token, key
+// management may add parameters to the header
+fn wrap_key(header: &mut JweHeader, key: &KeyMaterial) -> Result<(Vec<u8>, Vec<u8>)> {
+    match (header.alg, key) {
+        (KeyManagement::Dir, KeyMaterial::Secret(secret)) => {
+            if secret.len() != header.enc.key_len() {
+                return Err(wrong_key_len(header.enc));
+            }
+            Ok((secret.clone(), Vec::new()))
+        }
+        (KeyManagement::RSA_OAEP, KeyMaterial::Rsa {..})
+            | (KeyManagement::RSA_OAEP_256, KeyMaterial::Rsa {..}) => {
+            let cek = backend::random(header.enc.key_len())?;
+            let encrypted_key = backend::rsa_oaep_encrypt(key, header.alg, &cek)?;
+            Ok((cek, encrypted_key))
+        }
+        (KeyManagement::A128KW, KeyMaterial::Secret(kek))
+            | (KeyManagement::A192KW, KeyMaterial::Secret(kek))
+            | (KeyManagement::A256KW, KeyMaterial::Secret(kek)) => {
+            check_kek(header.alg, kek)?;
+            let cek = backend::random(header.enc.key_len())?;
+            let encrypted_key = backend::aes_key_wrap(kek, &cek)?;
+            Ok((cek, encrypted_key))
+        }
+        (KeyManagement::A128GCMKW, KeyMaterial::Secret(kek))
+            | (KeyManagement::A192GCMKW, KeyMaterial::Secret(kek))
+            | (KeyManagement::A256GCMKW, KeyMaterial::Secret(kek)) => {
+            check_kek(header.alg, kek)?;
+            let cek = backend::random(header.enc.key_len())?;
+            let iv = backend::random(GCMKW_IV_LEN)?;
+            let (encrypted_key, tag) = backend::aes_gcm_encrypt(kek, &iv, &[], &cek)?;
+            header.iv = Some(base64_encode(&iv));
+            header.tag = Some(base64_encode(&tag));
+            Ok((cek, encrypted_key))
+        }
+        (alg, KeyMaterial::Ec {curve, ..}) if alg.is_ecdh() => {
+            let ephemeral = backend::ec_generate_key(*curve)?;
+            let z = backend::ecdh(&ephemeral, key)?;
+            header.epk = Some(Jwk::from_ec_material(&ephemeral)?);
+            let derived = ecdh_derived_key(header, &z)?;
+            match alg.kek_len() {
+                None => Ok((derived, Vec::new())),
+                Some(_) => {
+                    let cek = backend::random(header.enc.key_len())?;
+                    let encrypted_key = backend::aes_key_wrap(&derived, &cek)?;
+                    Ok((cek, encrypted_key))
+                }
+            }
+        }
+        _ => Err(Error::KeyFamilyMismatch),
+    }
+}
+
+fn unwrap_key(header: &JweHeader, key: &KeyMaterial, encrypted_key: &[u8]) -> Result<Vec<u8>> {
+    match (header.alg, key) {
+        (KeyManagement::Dir, KeyMaterial::Secret(secret)) => {
+            if !encrypted_key.is_empty() {
+                return Err(Error::MalformedSegment {index: 1});
+            }
+            if secret.len() != header.enc.key_len() {
+                return Err(wrong_key_len(header.enc));
+            }
+            Ok(secret.clone())
+        }
+        (KeyManagement::RSA_OAEP, KeyMaterial::Rsa {..})
+            | (KeyManagement::RSA_OAEP_256, KeyMaterial::Rsa {..}) => {
+            // RFC 7516 11.5: whatever is wrong with the encrypted key, go on
+            // with a random one, so the failure only ever shows as a content
+            // that does not decrypt, and tells nothing about the padding
+            let random = backend::random(header.enc.key_len())?;
+            match backend::rsa_oaep_decrypt(key, header.alg, encrypted_key) {
+                Ok(cek) => Ok(if cek.len() == random.len() { cek } else { random }),
+                Err(Error::UnsupportedAlgorithm) => Err(Error::UnsupportedAlgorithm),
+                Err(_) => Ok(random),
+            }
+        }
+        (KeyManagement::A128KW, KeyMaterial::Secret(kek))
+            | (KeyManagement::A192KW, KeyMaterial::Secret(kek))
+            | (KeyManagement::A256KW, KeyMaterial::Secret(kek)) => {
+            check_kek(header.alg, kek)?;
+            let cek = backend::aes_key_unwrap(kek, encrypted_key)?;
+            if cek.len() != header.enc.key_len() {
+                return Err(Error::DecryptionFailed);
+            }
+            Ok(cek)
+        }
+        (KeyManagement::A128GCMKW, KeyMaterial::Secret(kek))
+            | (KeyManagement::A192GCMKW, KeyMaterial::Secret(kek))
+            | (KeyManagement::A256GCMKW, KeyMaterial::Secret(kek)) => {
+            check_kek(header.alg, kek)?;
+            let iv = required_param(&header.iv)?;
+            let tag = required_param(&header.tag)?;
+            if iv.len() != GCMKW_IV_LEN || tag.len() != GCM_TAG_LEN {
+                return Err(Error::DecryptionFailed);
+            }
+            let cek = backend::aes_gcm_decrypt(kek, &iv, &[], encrypted_key, &tag)?;
+            if cek.len() != header.enc.key_len() {
+                return Err(Error::DecryptionFailed);
+            }
+            Ok(cek)
+        }
+        (alg, KeyMaterial::Ec {..}) if alg.is_ecdh() => {
+            let epk = ephemeral_key(header, key)?;
+            let z = backend::ecdh(key, &epk)?;
+            let derived = ecdh_derived_key(header, &z)?;
+            if alg.kek_len().is_none() {
+                if !encrypted_key.is_empty() {
+                    return Err(Error::MalformedSegment {index: 1});
+                }
+                return Ok(derived);
+            }
+            let cek = backend::aes_key_unwrap(&derived, encrypted_key)?;
+            if cek.len() != header.enc.key_len() {
+                return Err(Error::DecryptionFailed);
+            }
+            Ok(cek)
+        }
+        _ => Err(Error::KeyFamilyMismatch),
+    }
+}
+
+// RFC 7518 5.2.2.1, the HMAC of the AAD, the IV, the ciphertext and the bit
+// length of the AAD, truncated to half its size
+fn cbc_hmac_tag(alg: Algorithm, mac_key: &[u8], aad: &[u8], iv: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>> {
+    let mut input = Vec::with_capacity(aad.len() + iv.len() + ciphertext.len() + 8);
+    input.extend_from_slice(aad);
+    input.extend_from_slice(iv);
+    input.extend_from_slice(ciphertext);
+    input.extend_from_slice(&((aad.len() as u64) * 8).to_be_bytes());
+    let mut tag = backend::sign(&KeyMaterial::Secret(mac_key.to_vec()), alg, &input)?;
+    tag.truncate(mac_key.len());
+    Ok(tag)
+}
+
+/// the ciphertext and the authentication tag of `plaintext`
+pub(crate) fn encrypt_content(enc: ContentEncryption,
+                              cek: &[u8],
+                              iv: &[u8],
+                              aad: &[u8],
+                              plaintext: &[u8]) -> Result<(Vec<u8>, Vec<u8>)> {
+    if cek.len() != enc.key_len() {
+        return Err(wrong_key_len(enc));
+    }
+    match enc.hmac() {
+        None => backend::aes_gcm_encrypt(cek, iv, aad, plaintext),
+        Some(alg) => {
+            let (mac_key, enc_key) = cek.split_at(cek.len() / 2);
+            let ciphertext = backend::aes_cbc_encrypt(enc_key, iv, plaintext)?;
+            let tag = cbc_hmac_tag(alg, mac_key, aad, iv, &ciphertext)?;
+            Ok((ciphertext, tag))
+        }
+    }
+}
+
+/// the plaintext, once `tag` is checked
+pub(crate) fn decrypt_content(enc: ContentEncryption,
+                              cek: &[u8],
+                              iv: &[u8],
+                              aad: &[u8],
+                              ciphertext: &[u8],
+                              tag: &[u8]) -> Result<Vec<u8>> {
+    if cek.len() != enc.key_len() {
+        return Err(wrong_key_len(enc));
+    }
+    if iv.len() != enc.iv_len() || tag.len() != enc.tag_len() {
+        return Err(Error::DecryptionFailed);
+    }
+    match enc.hmac() {
+        None => backend::aes_gcm_decrypt(cek, iv, aad, ciphertext, tag),
+        Some(alg) => {
+            // the padding is only looked at once the tag is good, no padding oracle
+            let (mac_key, enc_key) = cek.split_at(cek.len() / 2);
+            let expected = cbc_hmac_tag(alg, mac_key, aad, iv, ciphertext)?;
+            if !constant_time_eq(&expected, tag) {
+                return Err(Error::DecryptionFailed);
+            }
+            backend::aes_cbc_decrypt(enc_key, iv, ciphertext)
+        }
+    }
+}
+
+/// the five parts of a JWE compact serialization
+struct JweSegments<'a> {
+    header: &'a str,
+    encrypted_key: &'a str,
+    iv: &'a str,
+    ciphertext: &'a str,
+    tag: &'a str,
+}
+
+impl<'a> JweSegments<'a> {
+    fn split(token: &'a str) -> Result<JweSegments<'a>> {
+        let vec: Vec<&str> = token.split('.').collect();
+        if vec.len() != 5 {
+            // the first segment that is missing, or the first extra one
+            return Err(Error::MalformedSegment {index: ::std::cmp::min(vec.len(), 5)});
+        }
+        Ok(JweSegments {
+            header: vec[0],
+            encrypted_key: vec[1],
+            iv: vec[2],
+            ciphertext: vec[3],
+            tag: vec[4],
+        })
+    }
+}
+
+/// encrypt a Claim to a compact JWE string that only the holder of the
+/// matching `DecryptionKey` can read
+///
+/// the content encryption key and the IV are fresh for every token
+pub fn encrypt<T: JWTStringConvertable>(body: &T,
+                                        key: &EncryptionKey,
+                                        header: &JweHeader) -> Result<String> {
+    let plaintext = base64_decode(&body.to_base64_str()?)?;
+
+    let mut header = header.clone();
+    let (cek, encrypted_key) = wrap_key(&mut header, &key.material)?;
+    let header_base64 = header.to_base64_str()?;
+    let iv = backend::random(header.enc.iv_len())?;
+    // the protected header, exactly as sent, is the additional authenticated data
+    let (ciphertext, tag) = encrypt_content(header.enc, &cek, &iv, header_base64.as_bytes(), &plaintext)?;
+
+    Ok([header_base64,
+        base64_encode(&encrypted_key),
+        base64_encode(&iv),
+        base64_encode(&ciphertext),
+        base64_encode(&tag)].join("."))
+}
+
+/// read the header of a compact JWE string, e.g. to pick the key named by
+/// `kid`, nothing in it can be trusted before the token is decrypted
+pub fn decrypt_header(token: &str) -> Result<JweHeader> {
+    let segments = JweSegments::split(token)?;
+    JweHeader::from_base64_str(segments.header)
+}
+
+/// decrypt a compact JWE string, the claims are only deserialized once the
+/// ciphertext and the header are authenticated
+///
+/// `alg` and `enc` must be in `validation`, the claims are not validated,
+/// pass them to `Validation::validate`
+pub fn decrypt<T: JWTStringConvertable>(token: &str,
+                                        key: &DecryptionKey,
+                                        validation: &JweValidation) -> Result<T> {
+    let (_, plaintext) = decrypt_plaintext(token, key, validation)?;
+    T::from_base64_str(&base64_encode(&plaintext))
+}
+
+/// the header and the plaintext bytes of a compact JWE string
+pub(crate) fn decrypt_plaintext(token: &str,
+                                key: &DecryptionKey,
+                                validation: &JweValidation) -> Result<(JweHeader, Vec<u8>)> {
+    let segments = JweSegments::split(token)?;
+    let header = JweHeader::from_base64_str(segments.header)?;
+    if !validation.algorithms.contains(&header.alg) {
+        return Err(Error::KeyManagementNotAllowed(header.alg));
+    }
+    if !validation.encryptions.contains(&header.enc) {
+        return Err(Error::EncryptionNotAllowed(header.enc));
+    }
+    // compressed plaintexts are not supported, nor is any extension the
+    // sender marked critical
+    if header.extra.contains_key("zip") || header.extra.contains_key("crit") {
+        return Err(Error::UnsupportedAlgorithm);
+    }
+
+    let encrypted_key = base64_decode(segments.encrypted_key)?;
+    let iv = base64_decode(segments.iv)?;
+    let ciphertext = base64_decode(segments.ciphertext)?;
+    let tag = base64_decode(segments.tag)?;
+
+    let cek = unwrap_key(&header, &key.material, &encrypted_key)?;
+    let plaintext = decrypt_content(header.enc, &cek, &iv, segments.header.as_bytes(), &ciphertext, &tag)?;
+    Ok((header, plaintext))
+}
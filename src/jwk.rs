@@ -0,0 +1,343 @@
+use super::errors::*;
+use serde_json::value::{Value, to_value};
+
+use super::header::{Algorithm, AlgorithmFamily};
+use super::key::{EncodingKey, DecodingKey, KeyPolicy};
+use super::material::{KeyMaterial, RsaPrivate, EcCurve, EdCurve};
+use super::backend;
+use super::utils::{base64_encode, base64_decode};
+
+/// # JSON Web Key
+/// a key as described in RFC 7517, the key material lives in `params` and
+/// keeps the base64url encoding it has in json
+///
+/// # Example
+/// ```
+/// extern crate serde_json;
+/// extern crate simple_jwt;
+///
+/// use simple_jwt::Jwk;
+///
+/// # fn main() {
+/// let jwk: Jwk = serde_json::from_str(r#"{
+///     "kty": "oct",
+///     "kid": "hmac",
+///     "k": "YSBzZWNyZXQgb2YgYXQgbGVhc3QgdGhpcnR5LXR3byBieXRlcw"
+/// }"#).unwrap();
+/// let key = jwk.to_decoding_key().unwrap();
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Jwk {
+    #[serde(rename = "use", default, skip_serializing_if = "Option::is_none")]
+    pub key_use: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_ops: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alg: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kid: Option<String>,
+    #[serde(flatten)]
+    pub params: KeyParameters,
+}
+
+/// key material of a `Jwk`, tagged by `kty`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kty")]
+pub enum KeyParameters {
+    #[serde(rename = "RSA")]
+    Rsa(RsaParameters),
+    #[serde(rename = "EC")]
+    Ec(EcParameters),
+    #[serde(rename = "oct")]
+    Oct(OctParameters),
+    #[serde(rename = "OKP")]
+    Okp(OkpParameters),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RsaParameters {
+    pub n: String,
+    pub e: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub d: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub p: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub q: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dp: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dq: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub qi: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EcParameters {
+    pub crv: String,
+    pub x: String,
+    pub y: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub d: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OctParameters {
+    pub k: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OkpParameters {
+    pub crv: String,
+    pub x: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub d: Option<String>,
+}
+
+/// # JSON Web Key Set
+/// the `{"keys": [...]}` document an identity provider publishes, used with
+/// `decode_with_jwks` to pick the key named by the `kid` of a token
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JwkSet {
+    pub keys: Vec<Jwk>,
+}
+
+impl JwkSet {
+    /// the first key whose `kid` is `kid`
+    pub fn find(&self, kid: &str) -> Option<&Jwk> {
+        self.keys.iter().find(|jwk| jwk.kid.as_ref().map(|k| k == kid).unwrap_or(false))
+    }
+}
+
+fn ec_curve(crv: &str) -> Result<EcCurve> {
+    EcCurve::from_name(crv).ok_or(Error::UnsupportedAlgorithm)
+}
+
+fn ed_curve(crv: &str) -> Result<EdCurve> {
+    EdCurve::from_name(crv).ok_or(Error::UnsupportedAlgorithm)
+}
+
+// RSA integers without their leading zeros
+fn integer(field: &str) -> Result<Vec<u8>> {
+    let bytes = base64_decode(field)?;
+    let skip = bytes.iter().take_while(|&&b| b == 0).count();
+    Ok(bytes[skip..].to_vec())
+}
+
+fn required(field: &Option<String>, name: &str) -> Result<String> {
+    match *field {
+        Some(ref v) => Ok(v.clone()),
+        None => Err(Error::InvalidJwk(format!("missing parameter '{}'", name))),
+    }
+}
+
+// RFC 7518 6.2.1.2 requires EC coordinates and private keys to keep their
+// leading zeros, shorter ones are rejected
+fn coordinate(field: &str, curve: EcCurve) -> Result<Vec<u8>> {
+    let bytes = base64_decode(field)?;
+    if bytes.len() != curve.coordinate_len() {
+        return Err(Error::InvalidJwk(format!("EC coordinates of {} must be {} bytes", curve.name(),
+                                             curve.coordinate_len())));
+    }
+    Ok(bytes)
+}
+
+fn rsa_material(params: &RsaParameters, private: bool) -> Result<KeyMaterial> {
+    let private = if private {
+        Some(RsaPrivate {
+            d: integer(&required(&params.d, "d")?)?,
+            p: integer(&required(&params.p, "p")?)?,
+            q: integer(&required(&params.q, "q")?)?,
+            dp: integer(&required(&params.dp, "dp")?)?,
+            dq: integer(&required(&params.dq, "dq")?)?,
+            qi: integer(&required(&params.qi, "qi")?)?,
+        })
+    } else {
+        None
+    };
+    Ok(KeyMaterial::Rsa {n: integer(&params.n)?, e: integer(&params.e)?, private})
+}
+
+fn ec_material(params: &EcParameters, private: bool) -> Result<KeyMaterial> {
+    let curve = ec_curve(&params.crv)?;
+    let d = if private {
+        Some(coordinate(&required(&params.d, "d")?, curve)?)
+    } else {
+        None
+    };
+    Ok(KeyMaterial::Ec {
+        curve,
+        x: coordinate(&params.x, curve)?,
+        y: coordinate(&params.y, curve)?,
+        d,
+    })
+}
+
+// a public key, or the public half of a private key
+fn public_material_from_pem(pem: &[u8], family: AlgorithmFamily) -> Result<KeyMaterial> {
+    let material = KeyMaterial::from_pem(pem)?.to_public();
+    if material.family() != family {
+        return Err(Error::KeyFamilyMismatch);
+    }
+    backend::check_key(&material)?;
+    Ok(material)
+}
+
+impl Jwk {
+    fn new(params: KeyParameters) -> Jwk {
+        Jwk {key_use: None, key_ops: None, alg: None, kid: None, params}
+    }
+
+    /// the public JWK of a RSA public or private key in PEM format
+    pub fn from_rsa_pem(pem: &[u8]) -> Result<Jwk> {
+        match public_material_from_pem(pem, AlgorithmFamily::Rsa)? {
+            KeyMaterial::Rsa {ref n, ref e, ..} => Ok(Jwk::new(KeyParameters::Rsa(RsaParameters {
+                n: base64_encode(n),
+                e: base64_encode(e),
+                d: None, p: None, q: None, dp: None, dq: None, qi: None,
+            }))),
+            _ => Err(Error::KeyFamilyMismatch),
+        }
+    }
+
+    /// the public JWK of an EC public or private key in PEM format
+    pub fn from_ec_pem(pem: &[u8]) -> Result<Jwk> {
+        Jwk::from_ec_material(&public_material_from_pem(pem, AlgorithmFamily::Ec)?)
+    }
+
+    // the public part only, e.g. the `epk` of a JWE header
+    pub(crate) fn from_ec_material(material: &KeyMaterial) -> Result<Jwk> {
+        match *material {
+            KeyMaterial::Ec {curve, ref x, ref y, ..} => Ok(Jwk::new(KeyParameters::Ec(EcParameters {
+                crv: curve.name().to_string(),
+                x: base64_encode(x),
+                y: base64_encode(y),
+                d: None,
+            }))),
+            _ => Err(Error::KeyFamilyMismatch),
+        }
+    }
+
+    // the public EC key of this JWK, its point is not checked yet
+    pub(crate) fn ec_public_material(&self) -> Result<KeyMaterial> {
+        match self.params {
+            KeyParameters::Ec(ref params) => ec_material(params, false),
+            _ => Err(Error::KeyFamilyMismatch),
+        }
+    }
+
+    // the key of a RSA or EC JWK, with its private parameters if `private`
+    pub(crate) fn key_material(&self, private: bool) -> Result<KeyMaterial> {
+        match self.params {
+            KeyParameters::Rsa(ref params) => rsa_material(params, private),
+            KeyParameters::Ec(ref params) => ec_material(params, private),
+            _ => Err(Error::KeyFamilyMismatch),
+        }
+    }
+
+    /// the public JWK (`kty` OKP) of an Ed25519 or Ed448 public or private
+    /// key in PEM format
+    pub fn from_ed_pem(pem: &[u8]) -> Result<Jwk> {
+        match public_material_from_pem(pem, AlgorithmFamily::Ed)? {
+            KeyMaterial::Ed {curve, ref x, ..} => Ok(Jwk::new(KeyParameters::Okp(OkpParameters {
+                crv: curve.name().to_string(),
+                x: base64_encode(x),
+                d: None,
+            }))),
+            _ => Err(Error::KeyFamilyMismatch),
+        }
+    }
+
+    /// the `AlgorithmFamily` of keys built from this JWK, `None` for kinds
+    /// this crate can not sign with, e.g. X25519 OKP keys
+    pub fn family(&self) -> Option<AlgorithmFamily> {
+        match self.params {
+            KeyParameters::Rsa(_) => Some(AlgorithmFamily::Rsa),
+            KeyParameters::Ec(_) => Some(AlgorithmFamily::Ec),
+            KeyParameters::Oct(_) => Some(AlgorithmFamily::Hmac),
+            KeyParameters::Okp(ref params) => {
+                EdCurve::from_name(&params.crv).map(|_| AlgorithmFamily::Ed)
+            }
+        }
+    }
+
+    /// whether this key may verify tokens signed with `alg`, it must be of
+    /// the family of `alg`, its own `alg`, if any, must be `alg`, its `use`,
+    /// if any, must be "sig" and its `key_ops`, if any, must hold "verify"
+    pub fn supports(&self, alg: Algorithm) -> Result<bool> {
+        if self.family() != Some(alg.family()) {
+            return Ok(false);
+        }
+        if matches!(self.key_use, Some(ref key_use) if key_use != "sig") {
+            return Ok(false);
+        }
+        if matches!(self.key_ops, Some(ref ops) if !ops.iter().any(|op| op == "verify")) {
+            return Ok(false);
+        }
+        match self.alg {
+            Some(ref name) => Ok(to_value(alg)? == Value::String(name.clone())),
+            None => Ok(true),
+        }
+    }
+
+    /// build a signing key, RSA and EC keys must contain the private parameters
+    pub fn to_encoding_key(&self) -> Result<EncodingKey> {
+        self.to_encoding_key_with_policy(&KeyPolicy::default())
+    }
+
+    /// build a signing key checked against `policy`
+    pub fn to_encoding_key_with_policy(&self, policy: &KeyPolicy) -> Result<EncodingKey> {
+        match self.params {
+            KeyParameters::Oct(ref params) => {
+                EncodingKey::from_secret_with_policy(&base64_decode(&params.k)?, policy)
+            }
+            KeyParameters::Rsa(ref params) => {
+                EncodingKey::from_material(AlgorithmFamily::Rsa, rsa_material(params, true)?, policy)
+            }
+            KeyParameters::Ec(ref params) => {
+                EncodingKey::from_material(AlgorithmFamily::Ec, ec_material(params, true)?, policy)
+            }
+            KeyParameters::Okp(ref params) => {
+                let curve = ed_curve(&params.crv)?;
+                let d = base64_decode(&required(&params.d, "d")?)?;
+                let x = base64_decode(&params.x)?;
+                // the public key is derived from `d`, it must be the one in `x`
+                if backend::ed_public_key(curve, &d)? != x {
+                    return Err(Error::InvalidJwk("'x' does not match 'd'".to_string()));
+                }
+                let material = KeyMaterial::Ed {curve, x, d: Some(d)};
+                EncodingKey::from_material(AlgorithmFamily::Ed, material, policy)
+            }
+        }
+    }
+
+    /// build a verifying key from the public parameters
+    pub fn to_decoding_key(&self) -> Result<DecodingKey> {
+        self.to_decoding_key_with_policy(&KeyPolicy::default())
+    }
+
+    /// build a verifying key checked against `policy`
+    pub fn to_decoding_key_with_policy(&self, policy: &KeyPolicy) -> Result<DecodingKey> {
+        match self.params {
+            KeyParameters::Oct(ref params) => {
+                DecodingKey::from_secret_with_policy(&base64_decode(&params.k)?, policy)
+            }
+            KeyParameters::Rsa(ref params) => {
+                DecodingKey::from_material(AlgorithmFamily::Rsa, rsa_material(params, false)?, policy)
+            }
+            KeyParameters::Ec(ref params) => {
+                DecodingKey::from_material(AlgorithmFamily::Ec, ec_material(params, false)?, policy)
+            }
+            KeyParameters::Okp(ref params) => {
+                let curve = ed_curve(&params.crv)?;
+                let x = base64_decode(&params.x)?;
+                if x.len() != curve.key_len() {
+                    return Err(Error::InvalidJwk("malformed parameter 'x'".to_string()));
+                }
+                let material = KeyMaterial::Ed {curve, x, d: None};
+                DecodingKey::from_material(AlgorithmFamily::Ed, material, policy)
+            }
+        }
+    }
+}
@@ -0,0 +1,370 @@
+use std::fmt;
+use std::sync::Arc;
+
+use super::errors::*;
+use super::header::{Algorithm, AlgorithmFamily};
+use super::material::KeyMaterial;
+use super::backend::{self, digest_len, not_private};
+
+/// # KeyPolicy
+/// the minimum strength of the keys this crate builds, checked when a key is
+/// constructed and, for HMAC secrets, again against the hash of the algorithm
+///
+/// the default rejects HMAC secrets shorter than the output of their hash and
+/// RSA moduli under 2048 bits, `KeyPolicy::insecure()` accepts any key
+///
+/// # Example
+/// ```
+/// use simple_jwt::{EncodingKey, KeyPolicy};
+///
+/// assert!(EncodingKey::from_secret(b"secret").is_err());
+/// let key = EncodingKey::from_secret_with_policy(b"secret", &KeyPolicy::insecure()).unwrap();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KeyPolicy {
+    /// HMAC secrets must be at least as long as the output of the hash, 32
+    /// bytes for HS256, 48 for HS384 and 64 for HS512, default to true
+    pub hmac_secret_as_long_as_hash: bool,
+    /// the smallest RSA modulus accepted, in bits, default to 2048
+    pub min_rsa_bits: u32,
+}
+
+impl Default for KeyPolicy {
+    fn default() -> KeyPolicy {
+        KeyPolicy {
+            hmac_secret_as_long_as_hash: true,
+            min_rsa_bits: 2048,
+        }
+    }
+}
+
+// the output of SHA-256, no HMAC algorithm accepts a shorter secret
+const MIN_HMAC_SECRET_LEN: usize = 32;
+
+impl KeyPolicy {
+    /// accept keys of any size, only meant for interop with legacy issuers
+    pub fn insecure() -> KeyPolicy {
+        KeyPolicy {
+            hmac_secret_as_long_as_hash: false,
+            min_rsa_bits: 0,
+        }
+    }
+
+    fn check_hmac_secret(&self, len: usize, required: usize) -> Result<()> {
+        if self.hmac_secret_as_long_as_hash && len < required {
+            return Err(Error::WeakHmacSecret {len, required});
+        }
+        Ok(())
+    }
+
+    fn check_material(&self, material: &KeyMaterial) -> Result<()> {
+        match material.rsa_bits() {
+            Some(bits) if bits < self.min_rsa_bits => {
+                Err(Error::WeakRsaKey {bits, required: self.min_rsa_bits})
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// # EncodingKey
+/// a parsed key used to sign tokens, bound to one `AlgorithmFamily`
+///
+/// the key material is parsed once on construction, cloning only bumps a
+/// reference count so one key can be shared between threads
+///
+/// # Example
+/// ```
+/// use simple_jwt::{encode_with_key, EncodingKey, Claim, Algorithm};
+///
+/// let key = EncodingKey::from_secret(b"a secret of at least thirty-two bytes").unwrap();
+/// let mut claim = Claim::default();
+/// claim.set_iss("some iss");
+/// let result = encode_with_key(&claim, &key, Algorithm::HS256).unwrap();
+/// ```
+#[derive(Clone)]
+pub struct EncodingKey {
+    family: AlgorithmFamily,
+    material: Arc<KeyMaterial>,
+    policy: KeyPolicy,
+}
+
+/// # DecodingKey
+/// a parsed key used to verify tokens, bound to one `AlgorithmFamily`, so a
+/// token can only be verified with algorithms of the family the key was
+/// created for
+///
+/// the key material is parsed once on construction, cloning only bumps a
+/// reference count so one key can be shared between threads
+///
+/// # Example
+/// ```
+/// use simple_jwt::{DecodingKey, AlgorithmFamily};
+///
+/// let key = DecodingKey::from_secret(b"a secret of at least thirty-two bytes").unwrap();
+/// assert_eq!(key.family(), AlgorithmFamily::Hmac);
+/// ```
+#[derive(Clone)]
+pub struct DecodingKey {
+    family: AlgorithmFamily,
+    // only the public parameters, or an HMAC secret
+    material: Arc<KeyMaterial>,
+    policy: KeyPolicy,
+}
+
+// HMAC secrets are checked against the hash of the algorithm they sign with
+fn check_hmac_strength(material: &KeyMaterial, policy: &KeyPolicy, alg: Algorithm) -> Result<()> {
+    if alg.family() != AlgorithmFamily::Hmac || !policy.hmac_secret_as_long_as_hash {
+        return Ok(());
+    }
+    match *material {
+        KeyMaterial::Secret(ref secret) => {
+            policy.check_hmac_secret(secret.len(), digest_len(alg).unwrap_or(MIN_HMAC_SECRET_LEN))
+        }
+        _ => Ok(()),
+    }
+}
+
+// the material must be of `family`, and valid for the enabled backends
+pub(crate) fn check_material(material: &KeyMaterial, family: AlgorithmFamily, policy: &KeyPolicy) -> Result<()> {
+    if material.family() != family {
+        return Err(Error::KeyFamilyMismatch);
+    }
+    policy.check_material(material)?;
+    backend::check_key(material)
+}
+
+// `encode` and `decode` take one `&str` for every algorithm, a PEM key is
+// never an HMAC secret: with the header switched to HS256 the RSA public key
+// a server decodes with would verify tokens anyone can sign
+fn legacy_hmac_secret(secret: &str) -> Result<&[u8]> {
+    if secret.trim_start().starts_with("-----BEGIN") {
+        return Err(Error::KeyFamilyMismatch);
+    }
+    Ok(secret.as_bytes())
+}
+
+pub(crate) fn private_material(material: KeyMaterial) -> Result<KeyMaterial> {
+    if material.is_private() {
+        Ok(material)
+    } else {
+        Err(not_private())
+    }
+}
+
+pub(crate) fn public_material(material: KeyMaterial) -> Result<KeyMaterial> {
+    if material.is_private() {
+        Err(Error::InvalidKey("a public key is required".to_string()))
+    } else {
+        Ok(material)
+    }
+}
+
+impl EncodingKey {
+    /// HMAC secret for HS256/384/512, at least 32 bytes long
+    pub fn from_secret(secret: &[u8]) -> Result<EncodingKey> {
+        EncodingKey::from_secret_with_policy(secret, &KeyPolicy::default())
+    }
+
+    /// HMAC secret for HS256/384/512, checked against `policy`
+    pub fn from_secret_with_policy(secret: &[u8], policy: &KeyPolicy) -> Result<EncodingKey> {
+        policy.check_hmac_secret(secret.len(), MIN_HMAC_SECRET_LEN)?;
+        Ok(EncodingKey::new(AlgorithmFamily::Hmac, KeyMaterial::Secret(secret.to_vec()), *policy))
+    }
+
+    /// RSA private key in PEM format, PKCS#1 or PKCS#8, for RS256/384/512 and PS256/384/512,
+    /// the modulus must have at least 2048 bits
+    pub fn from_rsa_pem(pem: &[u8]) -> Result<EncodingKey> {
+        EncodingKey::from_rsa_pem_with_policy(pem, &KeyPolicy::default())
+    }
+
+    /// RSA private key in PEM format, PKCS#1 or PKCS#8, checked against `policy`
+    pub fn from_rsa_pem_with_policy(pem: &[u8], policy: &KeyPolicy) -> Result<EncodingKey> {
+        let material = private_material(KeyMaterial::from_pem(pem)?)?;
+        EncodingKey::from_material(AlgorithmFamily::Rsa, material, policy)
+    }
+
+    /// EC private key in PEM format, SEC1 or PKCS#8, for ES256/384/512 and ES256K
+    pub fn from_ec_pem(pem: &[u8]) -> Result<EncodingKey> {
+        let material = private_material(KeyMaterial::from_pem(pem)?)?;
+        EncodingKey::from_material(AlgorithmFamily::Ec, material, &KeyPolicy::default())
+    }
+
+    /// RSA private key in DER format, PKCS#1 or PKCS#8, for RS256/384/512 and PS256/384/512,
+    /// the modulus must have at least 2048 bits
+    pub fn from_rsa_der(der: &[u8]) -> Result<EncodingKey> {
+        EncodingKey::from_rsa_der_with_policy(der, &KeyPolicy::default())
+    }
+
+    /// RSA private key in DER format, PKCS#1 or PKCS#8, checked against `policy`
+    pub fn from_rsa_der_with_policy(der: &[u8], policy: &KeyPolicy) -> Result<EncodingKey> {
+        let material = KeyMaterial::from_private_der(der)?;
+        EncodingKey::from_material(AlgorithmFamily::Rsa, material, policy)
+    }
+
+    /// EC private key in DER format, SEC1 or PKCS#8, for ES256/384/512 and ES256K
+    pub fn from_ec_der(der: &[u8]) -> Result<EncodingKey> {
+        let material = KeyMaterial::from_private_der(der)?;
+        EncodingKey::from_material(AlgorithmFamily::Ec, material, &KeyPolicy::default())
+    }
+
+    /// Ed25519 or Ed448 private key in PEM format (PKCS#8) for EdDSA
+    pub fn from_ed_pem(pem: &[u8]) -> Result<EncodingKey> {
+        let material = private_material(KeyMaterial::from_pem(pem)?)?;
+        EncodingKey::from_material(AlgorithmFamily::Ed, material, &KeyPolicy::default())
+    }
+
+    /// Ed25519 or Ed448 private key in DER format (PKCS#8) for EdDSA
+    pub fn from_ed_der(der: &[u8]) -> Result<EncodingKey> {
+        let material = KeyMaterial::from_private_der(der)?;
+        EncodingKey::from_material(AlgorithmFamily::Ed, material, &KeyPolicy::default())
+    }
+
+    /// interpret a `&str` secret the way `encode` always did: an HMAC secret
+    /// for HS256/384/512, unless it is a PEM key, a private key PEM otherwise
+    pub(crate) fn from_legacy_secret(secret: &str, alg: Algorithm, policy: &KeyPolicy) -> Result<EncodingKey> {
+        match alg.family() {
+            AlgorithmFamily::Hmac => EncodingKey::from_secret_with_policy(legacy_hmac_secret(secret)?, policy),
+            AlgorithmFamily::Rsa => EncodingKey::from_rsa_pem_with_policy(secret.as_bytes(), policy),
+            AlgorithmFamily::Ec => EncodingKey::from_ec_pem(secret.as_bytes()),
+            AlgorithmFamily::Ed => EncodingKey::from_ed_pem(secret.as_bytes()),
+            AlgorithmFamily::Unsecured => Err(Error::UnsecuredToken),
+        }
+    }
+
+    fn new(family: AlgorithmFamily, material: KeyMaterial, policy: KeyPolicy) -> EncodingKey {
+        EncodingKey {family, material: Arc::new(material), policy}
+    }
+
+    pub(crate) fn from_material(family: AlgorithmFamily,
+                                material: KeyMaterial,
+                                policy: &KeyPolicy) -> Result<EncodingKey> {
+        check_material(&material, family, policy)?;
+        Ok(EncodingKey::new(family, material, *policy))
+    }
+
+    pub fn family(&self) -> AlgorithmFamily {
+        self.family
+    }
+
+    /// whether the key is strong enough for `alg` under the policy it was built with
+    pub(crate) fn check_strength(&self, alg: Algorithm) -> Result<()> {
+        check_hmac_strength(&self.material, &self.policy, alg)
+    }
+
+    pub(crate) fn material(&self) -> &KeyMaterial {
+        &self.material
+    }
+}
+
+impl DecodingKey {
+    /// HMAC secret for HS256/384/512, at least 32 bytes long
+    pub fn from_secret(secret: &[u8]) -> Result<DecodingKey> {
+        DecodingKey::from_secret_with_policy(secret, &KeyPolicy::default())
+    }
+
+    /// HMAC secret for HS256/384/512, checked against `policy`
+    pub fn from_secret_with_policy(secret: &[u8], policy: &KeyPolicy) -> Result<DecodingKey> {
+        policy.check_hmac_secret(secret.len(), MIN_HMAC_SECRET_LEN)?;
+        Ok(DecodingKey {
+            family: AlgorithmFamily::Hmac,
+            material: Arc::new(KeyMaterial::Secret(secret.to_vec())),
+            policy: *policy,
+        })
+    }
+
+    /// RSA public key in PEM format (`BEGIN PUBLIC KEY`) for RS256/384/512 and PS256/384/512,
+    /// the modulus must have at least 2048 bits
+    pub fn from_rsa_pem(pem: &[u8]) -> Result<DecodingKey> {
+        DecodingKey::from_rsa_pem_with_policy(pem, &KeyPolicy::default())
+    }
+
+    /// RSA public key in PEM format (`BEGIN PUBLIC KEY`), checked against `policy`
+    pub fn from_rsa_pem_with_policy(pem: &[u8], policy: &KeyPolicy) -> Result<DecodingKey> {
+        let material = public_material(KeyMaterial::from_pem(pem)?)?;
+        DecodingKey::from_material(AlgorithmFamily::Rsa, material, policy)
+    }
+
+    /// EC public key in PEM format (`BEGIN PUBLIC KEY`) for ES256/384/512 and ES256K
+    pub fn from_ec_pem(pem: &[u8]) -> Result<DecodingKey> {
+        let material = public_material(KeyMaterial::from_pem(pem)?)?;
+        DecodingKey::from_material(AlgorithmFamily::Ec, material, &KeyPolicy::default())
+    }
+
+    /// RSA public key in DER format, SubjectPublicKeyInfo or PKCS#1, for RS256/384/512 and PS256/384/512,
+    /// the modulus must have at least 2048 bits
+    pub fn from_rsa_der(der: &[u8]) -> Result<DecodingKey> {
+        DecodingKey::from_rsa_der_with_policy(der, &KeyPolicy::default())
+    }
+
+    /// RSA public key in DER format, SubjectPublicKeyInfo or PKCS#1, checked against `policy`
+    pub fn from_rsa_der_with_policy(der: &[u8], policy: &KeyPolicy) -> Result<DecodingKey> {
+        let material = KeyMaterial::from_public_der(der)?;
+        DecodingKey::from_material(AlgorithmFamily::Rsa, material, policy)
+    }
+
+    /// EC public key in DER format (SubjectPublicKeyInfo) for ES256/384/512 and ES256K
+    pub fn from_ec_der(der: &[u8]) -> Result<DecodingKey> {
+        let material = KeyMaterial::from_public_der(der)?;
+        DecodingKey::from_material(AlgorithmFamily::Ec, material, &KeyPolicy::default())
+    }
+
+    /// Ed25519 or Ed448 public key in PEM format (`BEGIN PUBLIC KEY`) for EdDSA
+    pub fn from_ed_pem(pem: &[u8]) -> Result<DecodingKey> {
+        let material = public_material(KeyMaterial::from_pem(pem)?)?;
+        DecodingKey::from_material(AlgorithmFamily::Ed, material, &KeyPolicy::default())
+    }
+
+    /// Ed25519 or Ed448 public key in DER format (SubjectPublicKeyInfo) for EdDSA
+    pub fn from_ed_der(der: &[u8]) -> Result<DecodingKey> {
+        let material = KeyMaterial::from_public_der(der)?;
+        DecodingKey::from_material(AlgorithmFamily::Ed, material, &KeyPolicy::default())
+    }
+
+    /// interpret a `&str` secret the way `decode` always did: an HMAC secret
+    /// for HS256/384/512, unless it is a PEM key, a public key PEM otherwise
+    pub(crate) fn from_legacy_secret(secret: &str, alg: Algorithm, policy: &KeyPolicy) -> Result<DecodingKey> {
+        match alg.family() {
+            AlgorithmFamily::Hmac => DecodingKey::from_secret_with_policy(legacy_hmac_secret(secret)?, policy),
+            AlgorithmFamily::Rsa => DecodingKey::from_rsa_pem_with_policy(secret.as_bytes(), policy),
+            AlgorithmFamily::Ec => DecodingKey::from_ec_pem(secret.as_bytes()),
+            AlgorithmFamily::Ed => DecodingKey::from_ed_pem(secret.as_bytes()),
+            AlgorithmFamily::Unsecured => Err(Error::UnsecuredToken),
+        }
+    }
+
+    /// the private parameters of `material`, if any, are dropped
+    pub(crate) fn from_material(family: AlgorithmFamily,
+                                material: KeyMaterial,
+                                policy: &KeyPolicy) -> Result<DecodingKey> {
+        let material = material.to_public();
+        check_material(&material, family, policy)?;
+        Ok(DecodingKey {family, material: Arc::new(material), policy: *policy})
+    }
+
+    pub fn family(&self) -> AlgorithmFamily {
+        self.family
+    }
+
+    /// whether the key is strong enough for `alg` under the policy it was built with
+    pub(crate) fn check_strength(&self, alg: Algorithm) -> Result<()> {
+        check_hmac_strength(&self.material, &self.policy, alg)
+    }
+
+    pub(crate) fn material(&self) -> &KeyMaterial {
+        &self.material
+    }
+}
+
+// never print the key material
+impl fmt::Debug for EncodingKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("EncodingKey").field("family", &self.family).finish()
+    }
+}
+
+impl fmt::Debug for DecodingKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DecodingKey").field("family", &self.family).finish()
+    }
+}
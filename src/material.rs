@@ -0,0 +1,465 @@
+//! key material as JWK sees it, the components of a key as big-endian bytes,
+//! so that every crypto backend can build its own key type from it
+//!
+//! PEM and DER keys are read here, without any crypto library: PKCS#8,
+//! PKCS#1 and SEC1 private keys, SubjectPublicKeyInfo and PKCS#1 public keys
+
+use base64::{decode_config, STANDARD};
+
+use super::errors::*;
+use super::der;
+use super::header::{Algorithm, AlgorithmFamily};
+use super::backend;
+
+/// the curves of ES256/384/512 and ES256K
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EcCurve {
+    P256,
+    P384,
+    P521,
+    Secp256k1,
+}
+
+/// the curves of EdDSA
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdCurve {
+    Ed25519,
+    Ed448,
+}
+
+// the content of the OBJECT IDENTIFIERs, without tag and length
+const OID_RSA_ENCRYPTION: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01];
+const OID_EC_PUBLIC_KEY: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01];
+const OID_P256: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07];
+const OID_P384: &[u8] = &[0x2b, 0x81, 0x04, 0x00, 0x22];
+const OID_P521: &[u8] = &[0x2b, 0x81, 0x04, 0x00, 0x23];
+const OID_SECP256K1: &[u8] = &[0x2b, 0x81, 0x04, 0x00, 0x0a];
+const OID_ED25519: &[u8] = &[0x2b, 0x65, 0x70];
+const OID_ED448: &[u8] = &[0x2b, 0x65, 0x71];
+
+const EC_CURVES: &[EcCurve] = &[EcCurve::P256, EcCurve::P384, EcCurve::P521, EcCurve::Secp256k1];
+const ED_CURVES: &[EdCurve] = &[EdCurve::Ed25519, EdCurve::Ed448];
+
+impl EcCurve {
+    /// the curve `alg` signs with, `None` if `alg` is not ECDSA
+    pub fn for_algorithm(alg: Algorithm) -> Option<EcCurve> {
+        match alg {
+            Algorithm::ES256 => Some(EcCurve::P256),
+            Algorithm::ES384 => Some(EcCurve::P384),
+            Algorithm::ES512 => Some(EcCurve::P521),
+            Algorithm::ES256K => Some(EcCurve::Secp256k1),
+            _ => None,
+        }
+    }
+
+    /// the curve of a JWK `crv`
+    pub fn from_name(crv: &str) -> Option<EcCurve> {
+        EC_CURVES.iter().cloned().find(|curve| curve.name() == crv)
+    }
+
+    /// the JWK `crv` of the curve
+    pub fn name(self) -> &'static str {
+        match self {
+            EcCurve::P256 => "P-256",
+            EcCurve::P384 => "P-384",
+            EcCurve::P521 => "P-521",
+            EcCurve::Secp256k1 => "secp256k1",
+        }
+    }
+
+    /// the length of a coordinate, and of the private scalar, in bytes
+    pub fn coordinate_len(self) -> usize {
+        match self {
+            EcCurve::P256 | EcCurve::Secp256k1 => 32,
+            EcCurve::P384 => 48,
+            EcCurve::P521 => 66,
+        }
+    }
+
+    fn oid(self) -> &'static [u8] {
+        match self {
+            EcCurve::P256 => OID_P256,
+            EcCurve::P384 => OID_P384,
+            EcCurve::P521 => OID_P521,
+            EcCurve::Secp256k1 => OID_SECP256K1,
+        }
+    }
+
+    fn from_oid(oid: &[u8]) -> Result<EcCurve> {
+        EC_CURVES.iter().cloned().find(|curve| curve.oid() == oid).ok_or(Error::UnsupportedAlgorithm)
+    }
+}
+
+impl EdCurve {
+    /// the curve of a JWK `crv`
+    pub fn from_name(crv: &str) -> Option<EdCurve> {
+        ED_CURVES.iter().cloned().find(|curve| curve.name() == crv)
+    }
+
+    /// the JWK `crv` of the curve
+    pub fn name(self) -> &'static str {
+        match self {
+            EdCurve::Ed25519 => "Ed25519",
+            EdCurve::Ed448 => "Ed448",
+        }
+    }
+
+    /// the length of both the public and the private key in bytes
+    pub fn key_len(self) -> usize {
+        match self {
+            EdCurve::Ed25519 => 32,
+            EdCurve::Ed448 => 57,
+        }
+    }
+
+    fn oid(self) -> &'static [u8] {
+        match self {
+            EdCurve::Ed25519 => OID_ED25519,
+            EdCurve::Ed448 => OID_ED448,
+        }
+    }
+}
+
+/// the private parameters of a RSA key, with the CRT ones openssl and ring
+/// want, RustCrypto computes them again
+#[derive(Clone)]
+#[cfg_attr(not(any(feature = "openssl", feature = "ring")), allow(dead_code))]
+pub struct RsaPrivate {
+    pub d: Vec<u8>,
+    pub p: Vec<u8>,
+    pub q: Vec<u8>,
+    pub dp: Vec<u8>,
+    pub dq: Vec<u8>,
+    pub qi: Vec<u8>,
+}
+
+/// a key of any family, RSA integers keep no leading zeros, EC coordinates
+/// and scalars are padded to the length of their curve
+#[derive(Clone)]
+pub enum KeyMaterial {
+    Secret(Vec<u8>),
+    Rsa { n: Vec<u8>, e: Vec<u8>, private: Option<RsaPrivate> },
+    Ec { curve: EcCurve, x: Vec<u8>, y: Vec<u8>, d: Option<Vec<u8>> },
+    Ed { curve: EdCurve, x: Vec<u8>, d: Option<Vec<u8>> },
+}
+
+fn invalid(reason: &str) -> Error {
+    Error::InvalidKey(reason.to_string())
+}
+
+fn malformed() -> Error {
+    invalid("malformed DER")
+}
+
+fn tlv(tag: u8, input: &[u8]) -> Result<(&[u8], &[u8])> {
+    der::decode_tlv(tag, input).ok_or_else(malformed)
+}
+
+fn integer(input: &[u8]) -> Result<(Vec<u8>, &[u8])> {
+    let (content, rest) = tlv(der::TAG_INTEGER, input)?;
+    let magnitude = der::decode_unsigned_integer(content).ok_or_else(malformed)?;
+    let skip = magnitude.iter().take_while(|&&b| b == 0).count();
+    Ok((magnitude[skip..].to_vec(), rest))
+}
+
+fn small_integer(input: &[u8]) -> Result<(usize, &[u8])> {
+    let (value, rest) = integer(input)?;
+    if value.len() > 1 {
+        return Err(malformed());
+    }
+    Ok((value.first().cloned().unwrap_or(0) as usize, rest))
+}
+
+fn end(input: &[u8]) -> Result<()> {
+    if input.is_empty() { Ok(()) } else { Err(malformed()) }
+}
+
+/// left pad `bytes` with zeros to `len`, longer input is rejected
+pub fn pad(bytes: &[u8], len: usize) -> Result<Vec<u8>> {
+    if bytes.len() > len {
+        return Err(invalid("value too long for its curve"));
+    }
+    let mut result = vec![0u8; len - bytes.len()];
+    result.extend_from_slice(bytes);
+    Ok(result)
+}
+
+// only the uncompressed form, `04 || x || y`
+fn ec_point(curve: EcCurve, point: &[u8]) -> Result<(Vec<u8>, Vec<u8>)> {
+    let len = curve.coordinate_len();
+    match point.split_first() {
+        Some((&4, coordinates)) if coordinates.len() == 2 * len => {
+            let (x, y) = coordinates.split_at(len);
+            Ok((x.to_vec(), y.to_vec()))
+        }
+        Some((&2, _)) | Some((&3, _)) => Err(invalid("compressed EC points are not supported")),
+        _ => Err(invalid("malformed EC point")),
+    }
+}
+
+/// the SEC1 encoding of an EC point, `04 || x || y`
+#[cfg(any(feature = "ring", feature = "rust-crypto"))]
+pub fn ec_point_bytes(x: &[u8], y: &[u8]) -> Vec<u8> {
+    let mut point = Vec::with_capacity(1 + x.len() + y.len());
+    point.push(4);
+    point.extend_from_slice(x);
+    point.extend_from_slice(y);
+    point
+}
+
+fn ed_curve_from_oid(oid: &[u8]) -> Option<EdCurve> {
+    ED_CURVES.iter().cloned().find(|curve| curve.oid() == oid)
+}
+
+// the algorithm OID, its parameters, if any, and the rest of the input
+type AlgorithmIdentifier<'a> = (&'a [u8], Option<&'a [u8]>, &'a [u8]);
+
+fn algorithm_identifier(input: &[u8]) -> Result<AlgorithmIdentifier<'_>> {
+    let (seq, rest) = tlv(der::TAG_SEQUENCE, input)?;
+    let (oid, seq) = tlv(der::TAG_OID, seq)?;
+    let params = match der::decode_optional_tlv(der::TAG_OID, seq) {
+        Some((Some(params), seq)) => { end(seq)?; Some(params) }
+        Some((None, seq)) => {
+            // rsaEncryption carries an explicit NULL, or nothing at all
+            let (_, seq) = der::decode_optional_tlv(der::TAG_NULL, seq).ok_or_else(malformed)?;
+            end(seq)?;
+            None
+        }
+        None => return Err(malformed()),
+    };
+    Ok((oid, params, rest))
+}
+
+fn rsa_public_key(input: &[u8]) -> Result<KeyMaterial> {
+    let (seq, rest) = tlv(der::TAG_SEQUENCE, input)?;
+    end(rest)?;
+    let (n, seq) = integer(seq)?;
+    let (e, seq) = integer(seq)?;
+    end(seq)?;
+    Ok(KeyMaterial::Rsa {n, e, private: None})
+}
+
+fn rsa_private_key(input: &[u8]) -> Result<KeyMaterial> {
+    let (seq, rest) = tlv(der::TAG_SEQUENCE, input)?;
+    end(rest)?;
+    let (version, seq) = small_integer(seq)?;
+    if version != 0 {
+        return Err(invalid("multi-prime RSA keys are not supported"));
+    }
+    let (n, seq) = integer(seq)?;
+    let (e, seq) = integer(seq)?;
+    let (d, seq) = integer(seq)?;
+    let (p, seq) = integer(seq)?;
+    let (q, seq) = integer(seq)?;
+    let (dp, seq) = integer(seq)?;
+    let (dq, seq) = integer(seq)?;
+    let (qi, seq) = integer(seq)?;
+    end(seq)?;
+    Ok(KeyMaterial::Rsa {
+        n,
+        e,
+        private: Some(RsaPrivate {d, p, q, dp, dq, qi}),
+    })
+}
+
+// SEC1, `curve` is the one of the PKCS#8 wrapper, if any
+fn ec_private_key(input: &[u8], curve: Option<EcCurve>) -> Result<KeyMaterial> {
+    let (seq, rest) = tlv(der::TAG_SEQUENCE, input)?;
+    end(rest)?;
+    let (version, seq) = small_integer(seq)?;
+    if version != 1 {
+        return Err(malformed());
+    }
+    let (d, seq) = tlv(der::TAG_OCTET_STRING, seq)?;
+    let (params, seq) = der::decode_optional_tlv(der::tag_context(0), seq).ok_or_else(malformed)?;
+    let (public, seq) = der::decode_optional_tlv(der::tag_context(1), seq).ok_or_else(malformed)?;
+    end(seq)?;
+
+    let curve = match (params, curve) {
+        (Some(params), outer) => {
+            let (oid, rest) = tlv(der::TAG_OID, params)?;
+            end(rest)?;
+            let inner = EcCurve::from_oid(oid)?;
+            if outer.map(|outer| outer != inner).unwrap_or(false) {
+                return Err(invalid("conflicting EC curves"));
+            }
+            inner
+        }
+        (None, Some(outer)) => outer,
+        (None, None) => return Err(invalid("missing EC curve")),
+    };
+    let d = pad(d, curve.coordinate_len())?;
+    let (x, y) = match public {
+        Some(public) => {
+            let (bits, rest) = tlv(der::TAG_BIT_STRING, public)?;
+            end(rest)?;
+            ec_point(curve, der::decode_bit_string(bits).ok_or_else(malformed)?)?
+        }
+        None => backend::ec_public_key(curve, &d)?,
+    };
+    Ok(KeyMaterial::Ec {curve, x, y, d: Some(d)})
+}
+
+fn pkcs8_private_key(input: &[u8]) -> Result<KeyMaterial> {
+    let (seq, rest) = tlv(der::TAG_SEQUENCE, input)?;
+    end(rest)?;
+    let (version, seq) = small_integer(seq)?;
+    if version > 1 {
+        return Err(malformed());
+    }
+    let (oid, params, seq) = algorithm_identifier(seq)?;
+    let (private, seq) = tlv(der::TAG_OCTET_STRING, seq)?;
+    let (_, seq) = der::decode_optional_tlv(der::tag_context(0), seq).ok_or_else(malformed)?;
+    // RFC 5958 `[1] IMPLICIT BIT STRING`, a primitive context tag
+    let (public, seq) = der::decode_optional_tlv(0x81, seq).ok_or_else(malformed)?;
+    end(seq)?;
+
+    if oid == OID_RSA_ENCRYPTION {
+        return rsa_private_key(private);
+    }
+    if oid == OID_EC_PUBLIC_KEY {
+        let params = params.ok_or_else(|| invalid("missing EC curve"))?;
+        return ec_private_key(private, Some(EcCurve::from_oid(params)?));
+    }
+    let curve = ed_curve_from_oid(oid).ok_or(Error::UnsupportedAlgorithm)?;
+    let (seed, rest) = tlv(der::TAG_OCTET_STRING, private)?;
+    end(rest)?;
+    if seed.len() != curve.key_len() {
+        return Err(invalid("malformed EdDSA private key"));
+    }
+    let x = match public {
+        Some(public) => der::decode_bit_string(public).ok_or_else(malformed)?.to_vec(),
+        None => backend::ed_public_key(curve, seed)?,
+    };
+    Ok(KeyMaterial::Ed {curve, x, d: Some(seed.to_vec())})
+}
+
+fn subject_public_key_info(input: &[u8]) -> Result<KeyMaterial> {
+    let (seq, rest) = tlv(der::TAG_SEQUENCE, input)?;
+    end(rest)?;
+    let (oid, params, seq) = algorithm_identifier(seq)?;
+    let (bits, seq) = tlv(der::TAG_BIT_STRING, seq)?;
+    end(seq)?;
+    let key = der::decode_bit_string(bits).ok_or_else(malformed)?;
+
+    if oid == OID_RSA_ENCRYPTION {
+        return rsa_public_key(key);
+    }
+    if oid == OID_EC_PUBLIC_KEY {
+        let params = params.ok_or_else(|| invalid("missing EC curve"))?;
+        let curve = EcCurve::from_oid(params)?;
+        let (x, y) = ec_point(curve, key)?;
+        return Ok(KeyMaterial::Ec {curve, x, y, d: None});
+    }
+    let curve = ed_curve_from_oid(oid).ok_or(Error::UnsupportedAlgorithm)?;
+    if key.len() != curve.key_len() {
+        return Err(invalid("malformed EdDSA public key"));
+    }
+    Ok(KeyMaterial::Ed {curve, x: key.to_vec(), d: None})
+}
+
+// the DER of the first PEM block with a key label, and that label
+fn pem_to_der(pem: &[u8]) -> Result<(String, Vec<u8>)> {
+    let text = ::std::str::from_utf8(pem).map_err(|_| invalid("PEM is not text"))?;
+    let mut lines = text.lines().map(str::trim);
+    while let Some(line) = lines.next() {
+        let label = match line.strip_prefix("-----BEGIN ").and_then(|l| l.strip_suffix("-----")) {
+            Some(label) => label,
+            None => continue,
+        };
+        if !label.ends_with("KEY") {
+            continue;
+        }
+        let end_line = format!("-----END {}-----", label);
+        let mut body = String::new();
+        for line in lines.by_ref() {
+            if line == end_line {
+                let der = decode_config(&body, STANDARD)?;
+                return Ok((label.to_string(), der));
+            }
+            // `Proc-Type: 4,ENCRYPTED` and friends
+            if line.contains(':') {
+                return Err(invalid("encrypted PEM keys are not supported"));
+            }
+            body.push_str(line);
+        }
+        return Err(invalid("unterminated PEM block"));
+    }
+    Err(invalid("no key in PEM"))
+}
+
+impl KeyMaterial {
+    /// a private or public key in PEM format
+    pub fn from_pem(pem: &[u8]) -> Result<KeyMaterial> {
+        let (label, der) = pem_to_der(pem)?;
+        match label.as_str() {
+            "PRIVATE KEY" => pkcs8_private_key(&der),
+            "RSA PRIVATE KEY" => rsa_private_key(&der),
+            "EC PRIVATE KEY" => ec_private_key(&der, None),
+            "PUBLIC KEY" => subject_public_key_info(&der),
+            "RSA PUBLIC KEY" => rsa_public_key(&der),
+            "ENCRYPTED PRIVATE KEY" => Err(invalid("encrypted PEM keys are not supported")),
+            _ => Err(invalid("unknown PEM label")),
+        }
+    }
+
+    /// a private key in DER format, PKCS#8, PKCS#1 or SEC1
+    pub fn from_private_der(der: &[u8]) -> Result<KeyMaterial> {
+        pkcs8_private_key(der)
+            .or_else(|_| rsa_private_key(der))
+            .or_else(|_| ec_private_key(der, None))
+            .map_err(|_| invalid("not a PKCS#8, PKCS#1 or SEC1 private key"))
+    }
+
+    /// a public key in DER format, SubjectPublicKeyInfo or PKCS#1
+    pub fn from_public_der(der: &[u8]) -> Result<KeyMaterial> {
+        subject_public_key_info(der)
+            .or_else(|_| rsa_public_key(der))
+            .map_err(|_| invalid("not a SubjectPublicKeyInfo or PKCS#1 public key"))
+    }
+
+    pub fn family(&self) -> AlgorithmFamily {
+        match *self {
+            KeyMaterial::Secret(_) => AlgorithmFamily::Hmac,
+            KeyMaterial::Rsa {..} => AlgorithmFamily::Rsa,
+            KeyMaterial::Ec {..} => AlgorithmFamily::Ec,
+            KeyMaterial::Ed {..} => AlgorithmFamily::Ed,
+        }
+    }
+
+    /// whether the key can sign, HMAC secrets always can
+    pub fn is_private(&self) -> bool {
+        match *self {
+            KeyMaterial::Secret(_) => true,
+            KeyMaterial::Rsa {ref private, ..} => private.is_some(),
+            KeyMaterial::Ec {ref d, ..} | KeyMaterial::Ed {ref d, ..} => d.is_some(),
+        }
+    }
+
+    /// the key without its private parameters, HMAC secrets stay as they are
+    pub fn to_public(&self) -> KeyMaterial {
+        match *self {
+            KeyMaterial::Secret(ref secret) => KeyMaterial::Secret(secret.clone()),
+            KeyMaterial::Rsa {ref n, ref e, ..} => {
+                KeyMaterial::Rsa {n: n.clone(), e: e.clone(), private: None}
+            }
+            KeyMaterial::Ec {curve, ref x, ref y, ..} => {
+                KeyMaterial::Ec {curve, x: x.clone(), y: y.clone(), d: None}
+            }
+            KeyMaterial::Ed {curve, ref x, ..} => {
+                KeyMaterial::Ed {curve, x: x.clone(), d: None}
+            }
+        }
+    }
+
+    /// the size of the modulus of a RSA key in bits
+    pub fn rsa_bits(&self) -> Option<u32> {
+        match *self {
+            KeyMaterial::Rsa {ref n, ..} => {
+                let leading = n.first().map(|b| b.leading_zeros()).unwrap_or(0);
+                Some(n.len() as u32 * 8 - leading)
+            }
+            _ => None,
+        }
+    }
+}
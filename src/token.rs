@@ -0,0 +1,123 @@
+use std::marker::PhantomData;
+
+use super::errors::*;
+use super::header::{Header, JoseHeader};
+use super::claim::Claim;
+use super::key::{EncodingKey, DecodingKey};
+use super::validation::Validation;
+use super::utils::JWTStringConvertable;
+use super::{encode_with_header, decode_segments, Segments};
+
+/// state of a `Token` read from a string whose signature was not checked yet
+#[derive(Debug, Clone, Copy)]
+pub enum Unverified {}
+
+/// state of a `Token` whose signature and claims were checked
+#[derive(Debug, Clone, Copy)]
+pub enum Verified {}
+
+/// state of a `Token` this crate signed
+#[derive(Debug, Clone, Copy)]
+pub enum Signed {}
+
+/// # Token
+/// a jwt whose state is part of its type, the claims of a token read from a
+/// string are only reachable once it went through `verify`
+///
+/// the header is always readable, it is what picks the key
+///
+/// # Example
+/// ```
+/// use simple_jwt::{Token, Header, Claim, Algorithm, EncodingKey, DecodingKey, Validation};
+///
+/// let secret = b"a secret of at least thirty-two bytes";
+/// let mut claim = Claim::default();
+/// claim.set_iss("some iss");
+/// let signed = Token::sign(Header::new(Algorithm::HS256), claim,
+///                          &EncodingKey::from_secret(secret).unwrap()).unwrap();
+///
+/// let token: Token = Token::parse(signed.as_str()).unwrap();
+/// assert_eq!(token.header().alg, Algorithm::HS256);
+/// let token = token.verify(&DecodingKey::from_secret(secret).unwrap(),
+///                          &Validation::new(Algorithm::HS256)).unwrap();
+/// assert_eq!(token.claims().registered.iss, Some("some iss".to_string()));
+/// ```
+///
+/// the claims of an unverified token do not exist for the compiler
+///
+/// ```compile_fail
+/// use simple_jwt::Token;
+///
+/// let token: Token = Token::parse("eyJhbGciOiJIUzI1NiIsInR5cCI6IkpXVCJ9.e30.YWJj").unwrap();
+/// let claims = token.claims();
+/// ```
+#[derive(Debug, Clone)]
+pub struct Token<H = Header, C = Claim, S = Unverified> {
+    header: H,
+    // only ever `None` in the `Unverified` state
+    claims: Option<C>,
+    jwtstr: String,
+    state: PhantomData<S>,
+}
+
+impl<H, C, S> Token<H, C, S> {
+    /// the header, which is not trusted before `verify`
+    pub fn header(&self) -> &H {
+        &self.header
+    }
+
+    /// the jwt string
+    pub fn as_str(&self) -> &str {
+        &self.jwtstr
+    }
+
+    fn into_state<T>(self) -> Token<H, C, T> {
+        Token {header: self.header, claims: self.claims, jwtstr: self.jwtstr, state: PhantomData}
+    }
+}
+
+impl<H: JWTStringConvertable, C: JWTStringConvertable> Token<H, C, Unverified> {
+    /// split `jwtstr` and read its header, the claims are left alone
+    pub fn parse(jwtstr: &str) -> Result<Token<H, C, Unverified>> {
+        // padding is only judged by `verify`, once the `Validation` is known
+        let segments = Segments::split(jwtstr, true)?;
+        let header = H::from_base64_str(segments.header)?;
+        Ok(Token {header, claims: None, jwtstr: jwtstr.to_string(), state: PhantomData})
+    }
+
+    /// check the token like `decode_with_validation` does, with the `alg`
+    /// and `crit` of its own header, and only then deserialize its claims
+    pub fn verify(self, key: &DecodingKey, validation: &Validation) -> Result<Token<H, C, Verified>>
+        where H: JoseHeader {
+        let claims = {
+            let segments = Segments::split(&self.jwtstr, validation.lenient_base64)?;
+            decode_segments(&segments, &self.header, key, validation)?
+        };
+        let mut token = self.into_state();
+        token.claims = Some(claims);
+        Ok(token)
+    }
+}
+
+impl<H, C> Token<H, C, Verified> {
+    /// the claims, checked against the `Validation` given to `verify`
+    pub fn claims(&self) -> &C {
+        self.claims.as_ref().expect("a verified token always has claims")
+    }
+
+    pub fn into_claims(self) -> C {
+        self.claims.expect("a verified token always has claims")
+    }
+}
+
+impl<C: JWTStringConvertable> Token<Header, C, Signed> {
+    /// sign `claims` with `key`, the family of `key` must match `header.alg`
+    pub fn sign(header: Header, claims: C, key: &EncodingKey) -> Result<Token<Header, C, Signed>> {
+        let jwtstr = encode_with_header(&claims, key, &header)?;
+        Ok(Token {header, claims: Some(claims), jwtstr, state: PhantomData})
+    }
+
+    pub fn into_string(self) -> String {
+        self.jwtstr
+    }
+}
@@ -0,0 +1,74 @@
+use super::errors::*;
+use super::header::Header;
+use super::claim::Claim;
+use super::utils::{JWTStringConvertable, base64_decode};
+use super::Segments;
+
+/// # UnverifiedToken
+/// a jwt string split into its parts, with *no* signature check and *no*
+/// claim validation, for tools that must look into a token without its key,
+/// e.g. to log the `iss` or to pick the key to decode it with
+///
+/// nothing read from it can be trusted, decode the token with
+/// `decode_with_validation` before acting on its content
+///
+/// # Example
+/// ```
+/// use simple_jwt::{encode, UnverifiedToken, Claim, Algorithm};
+///
+/// let mut claim = Claim::default();
+/// claim.set_iss("some iss");
+/// let result = encode(&claim, "a secret of at least thirty-two bytes", Algorithm::HS256).unwrap();
+///
+/// let token = UnverifiedToken::parse(&result).unwrap();
+/// assert_eq!(token.header().alg, Algorithm::HS256);
+/// assert_eq!(token.unverified_claim().unwrap().registered.iss, Some("some iss".to_string()));
+/// ```
+#[derive(Debug, Clone)]
+pub struct UnverifiedToken<'a> {
+    header: Header,
+    body: &'a str,
+    signing_input: &'a str,
+    signature: Vec<u8>,
+}
+
+impl<'a> UnverifiedToken<'a> {
+    /// split `jwtstr` and decode its header and signature, the payload is
+    /// only deserialized by the accessors
+    pub fn parse(jwtstr: &'a str) -> Result<UnverifiedToken<'a>> {
+        let segments = Segments::split(jwtstr, false)?;
+        let header = Header::from_base64_str(segments.header)?;
+        let signature = base64_decode(segments.sig)?;
+        Ok(UnverifiedToken {
+            header,
+            body: segments.body,
+            signing_input: segments.signing_input,
+            signature,
+        })
+    }
+
+    /// the header, as sent
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// the payload as a `Claim`, unverified
+    pub fn unverified_claim(&self) -> Result<Claim> {
+        Claim::from_base64_str(self.body)
+    }
+
+    /// the payload as any `T`, unverified
+    pub fn unverified_payload<T: JWTStringConvertable>(&self) -> Result<T> {
+        T::from_base64_str(self.body)
+    }
+
+    /// the first two segments, which the signature covers
+    pub fn signing_input(&self) -> &'a str {
+        self.signing_input
+    }
+
+    /// the decoded signature bytes, empty for an unsecured token
+    pub fn signature(&self) -> &[u8] {
+        &self.signature
+    }
+}
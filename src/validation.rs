@@ -0,0 +1,122 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::errors::*;
+use super::claim::Claim;
+use super::header::Algorithm;
+
+/// # Validation
+/// options used by `decode_with_validation` to check the claims of a token
+/// after its signature has been verified
+///
+/// # Example
+/// ```
+/// use simple_jwt::{Validation, Algorithm};
+///
+/// let mut validation = Validation::new(Algorithm::RS256);
+/// validation.algorithms.push(Algorithm::RS512);
+/// validation.leeway = 60;
+/// validation.required_claims = vec!["exp".to_string(), "iss".to_string()];
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Validation {
+    /// algorithms a token may be signed with, anything else in `Header.alg`
+    /// is rejected before the signature is checked, default to HS256 only
+    pub algorithms: Vec<Algorithm>,
+    /// seconds of clock skew tolerated when checking `exp`, `nbf` and `iat`
+    pub leeway: u64,
+    /// reject the token if `exp` is in the past, default to true
+    pub validate_exp: bool,
+    /// reject the token if `nbf` is in the future, default to true
+    pub validate_nbf: bool,
+    /// reject the token if `iat` is in the future, default to false
+    pub validate_iat: bool,
+    /// claims that must be present in the token, registered or private
+    pub required_claims: Vec<String>,
+    /// when set, `aud` must contain at least one of these audiences
+    pub aud: Option<Vec<String>>,
+    /// accept segments with `=` padding, as issued by this crate before it
+    /// followed RFC 7515, default to false
+    pub lenient_base64: bool,
+}
+
+impl Default for Validation {
+    fn default() -> Validation {
+        Validation {
+            algorithms: vec![Algorithm::HS256],
+            leeway: 0,
+            validate_exp: true,
+            validate_nbf: true,
+            validate_iat: false,
+            required_claims: Vec::new(),
+            aud: None,
+            lenient_base64: false,
+        }
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+impl Validation {
+    /// default options that only allow `alg`
+    pub fn new(alg: Algorithm) -> Validation {
+        Validation {algorithms: vec![alg], ..Validation::default()}
+    }
+
+    /// check `claim` against these options using the current system time
+    pub fn validate(&self, claim: &Claim) -> Result<()> {
+        self.validate_at(claim, now())
+    }
+
+    /// check `claim` against these options, `now` is seconds since the unix epoch
+    pub fn validate_at(&self, claim: &Claim, now: u64) -> Result<()> {
+        for name in &self.required_claims {
+            if !claim.has_claim(name) {
+                return Err(Error::MissingRequiredClaim(name.clone()));
+            }
+        }
+
+        let reg = &claim.registered;
+        if self.validate_exp {
+            if let Some(exp) = reg.exp {
+                if exp.saturating_add(self.leeway) <= now {
+                    return Err(Error::Expired);
+                }
+            }
+        }
+        if self.validate_nbf {
+            if let Some(nbf) = reg.nbf {
+                if nbf > now.saturating_add(self.leeway) {
+                    return Err(Error::Immature);
+                }
+            }
+        }
+        if self.validate_iat {
+            if let Some(iat) = reg.iat {
+                if iat > now.saturating_add(self.leeway) {
+                    return Err(Error::InvalidIssuedAt);
+                }
+            }
+        }
+        if let Some(ref expected) = self.aud {
+            let matched = match reg.aud {
+                Some(ref aud) => expected.iter().any(|e| aud.contains(e)),
+                None => false,
+            };
+            if !matched {
+                return Err(Error::InvalidAudience);
+            }
+        }
+        Ok(())
+    }
+
+    /// only accept tokens for one of `audiences`
+    pub fn set_audience(&mut self, audiences: &[&str]) -> &mut Validation {
+        self.aud = Some(audiences.iter().map(|s| s.to_string()).collect());
+        self
+    }
+}